@@ -30,7 +30,7 @@ mod tests {
 
     #[test]
     fn select_message_before_timeout() {
-        let (mut tx, rx) = sync::mpsc::channel::<u32>(16);
+        let (tx, rx) = sync::mpsc::channel::<u32>(16);
 
         let mut select_task = select_task(rx);
 
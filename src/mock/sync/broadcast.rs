@@ -1,2 +1,741 @@
-// It looks ok to just use tokio's broadcast
-pub use tokio::sync::broadcast::*;
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::future::Future;
+use std::panic::Location;
+use std::pin::Pin;
+use std::sync::{Arc, Condvar, Mutex};
+use std::task::{Context, Poll, Waker};
+
+use tokio::sync::broadcast::error::{RecvError, SendError, TryRecvError};
+
+pub use tokio::sync::broadcast::error;
+
+use super::{register, wait_for};
+
+#[derive(Debug)]
+struct ReceiverState {
+    name: String,
+    // position of the next message to receive
+    next: u64,
+    // messages skipped by the probe, reported on the next receive
+    forced_lag: u64,
+    waker: Option<Waker>,
+}
+
+impl ReceiverState {
+    // like tokio, lost messages count until the receiver is told about the lag
+    fn unread(&self, tail: u64) -> usize {
+        (tail - self.next + self.forced_lag) as usize
+    }
+}
+
+#[derive(Debug)]
+struct ChannelData<T> {
+    // messages at positions head..tail, kept until every receiver has seen them
+    buffer: VecDeque<T>,
+    head: u64,
+    tail: u64,
+    capacity: usize,
+    num_senders: usize,
+    num_weak_senders: usize,
+    receivers: BTreeMap<u64, ReceiverState>,
+    next_id: u64,
+    closed_wakers: Vec<Waker>,
+    // notified alongside the wakers for threads in blocking_recv
+    blocked: Arc<Condvar>,
+}
+
+impl<T> ChannelData<T> {
+    #[track_caller]
+    fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "broadcast channel capacity cannot be zero");
+
+        Self {
+            buffer: VecDeque::new(),
+            head: 0,
+            tail: 0,
+            // tokio rounds up as well, which shows in the lag counts
+            capacity: capacity.next_power_of_two(),
+            num_senders: 1,
+            num_weak_senders: 0,
+            receivers: BTreeMap::new(),
+            next_id: 0,
+            closed_wakers: Vec::new(),
+            blocked: Arc::new(Condvar::new()),
+        }
+    }
+
+    fn subscribe(&mut self, location: &'static Location<'static>) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.receivers.insert(
+            id,
+            ReceiverState {
+                name: format!("{}:{}", location.file(), location.line()),
+                next: self.tail,
+                forced_lag: 0,
+                waker: None,
+            },
+        );
+        id
+    }
+
+    fn unsubscribe(&mut self, id: u64) {
+        self.receivers.remove(&id);
+        self.trim();
+        if self.receivers.is_empty() {
+            for waker in self.closed_wakers.drain(..) {
+                waker.wake();
+            }
+        }
+    }
+
+    fn send(&mut self, value: T) -> Result<usize, SendError<T>> {
+        if self.receivers.is_empty() {
+            return Err(SendError(value));
+        }
+
+        self.buffer.push_back(value);
+        self.tail += 1;
+        if self.buffer.len() > self.capacity {
+            self.buffer.pop_front();
+            self.head += 1;
+        }
+
+        self.wake_receivers();
+        Ok(self.receivers.len())
+    }
+
+    fn try_recv(&mut self, id: u64) -> Result<T, TryRecvError>
+    where
+        T: Clone,
+    {
+        let head = self.head;
+        let receiver = self.receivers.get_mut(&id).unwrap();
+
+        // overwritten messages are skipped, resuming from the oldest one still stored
+        let missed = head.saturating_sub(receiver.next) + std::mem::take(&mut receiver.forced_lag);
+        receiver.next = receiver.next.max(head);
+        if missed > 0 {
+            return Err(TryRecvError::Lagged(missed));
+        }
+
+        if receiver.next == self.tail {
+            return Err(if self.num_senders == 0 {
+                TryRecvError::Closed
+            } else {
+                TryRecvError::Empty
+            });
+        }
+
+        let value = self.buffer[(receiver.next - head) as usize].clone();
+        receiver.next += 1;
+        self.trim();
+        Ok(value)
+    }
+
+    fn poll_recv(&mut self, id: u64, cx: &mut Context<'_>) -> Poll<Result<T, RecvError>>
+    where
+        T: Clone,
+    {
+        match self.try_recv(id) {
+            Ok(value) => Poll::Ready(Ok(value)),
+            Err(TryRecvError::Lagged(missed)) => Poll::Ready(Err(RecvError::Lagged(missed))),
+            Err(TryRecvError::Closed) => Poll::Ready(Err(RecvError::Closed)),
+            Err(TryRecvError::Empty) => {
+                self.receivers.get_mut(&id).unwrap().waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+
+    fn poll_closed(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        if self.receivers.is_empty() {
+            Poll::Ready(())
+        } else {
+            register(&mut self.closed_wakers, cx.waker());
+            Poll::Pending
+        }
+    }
+
+    // drops the messages every receiver has already seen
+    fn trim(&mut self) {
+        let oldest = self
+            .receivers
+            .values()
+            .map(|receiver| receiver.next)
+            .min()
+            .unwrap_or(self.tail);
+
+        while self.head < oldest {
+            self.buffer.pop_front();
+            self.head += 1;
+        }
+    }
+
+    fn unread(&self, id: u64) -> usize {
+        self.receivers[&id].unread(self.tail)
+    }
+
+    fn lag(&mut self, name: &str, count: u64) -> u64 {
+        let (head, tail) = (self.head, self.tail);
+        let mut skipped = 0;
+
+        for receiver in self.receivers.values_mut() {
+            if receiver.name == name {
+                let next = receiver.next.max(head);
+                let skip = count.min(tail - next);
+                receiver.forced_lag += head.saturating_sub(receiver.next) + skip;
+                receiver.next = next + skip;
+                skipped += skip;
+            }
+        }
+
+        self.trim();
+        skipped
+    }
+
+    fn upgrade(&mut self) -> bool {
+        if self.num_senders == 0 {
+            return false;
+        }
+        self.num_senders += 1;
+        true
+    }
+
+    fn remove_sender(&mut self) {
+        self.num_senders -= 1;
+        if self.num_senders == 0 {
+            self.wake_receivers();
+        }
+    }
+
+    fn wake_receivers(&mut self) {
+        for receiver in self.receivers.values_mut() {
+            if let Some(waker) = receiver.waker.take() {
+                waker.wake();
+            }
+        }
+        self.blocked.notify_all();
+    }
+}
+
+#[track_caller]
+pub fn channel<T: Clone>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    let data = Arc::new(Mutex::new(ChannelData::new(capacity)));
+    let rx = Receiver::new(data.clone(), Location::caller());
+
+    (Sender { data }, rx)
+}
+
+pub struct Sender<T> {
+    data: Arc<Mutex<ChannelData<T>>>,
+}
+
+impl<T> Sender<T> {
+    /// Sender of a new channel without any receiver yet
+    #[track_caller]
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            data: Arc::new(Mutex::new(ChannelData::new(capacity))),
+        }
+    }
+
+    /// Returns the number of receivers the value was sent to
+    pub fn send(&self, value: T) -> Result<usize, SendError<T>> {
+        self.data.lock().unwrap().send(value)
+    }
+
+    #[track_caller]
+    pub fn subscribe(&self) -> Receiver<T> {
+        Receiver::new(self.data.clone(), Location::caller())
+    }
+
+    /// Number of messages not yet seen by every receiver
+    pub fn len(&self) -> usize {
+        self.data.lock().unwrap().buffer.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn receiver_count(&self) -> usize {
+        self.data.lock().unwrap().receivers.len()
+    }
+
+    pub fn same_channel(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.data, &other.data)
+    }
+
+    /// Create a sender that does not keep the channel open
+    pub fn downgrade(&self) -> WeakSender<T> {
+        self.data.lock().unwrap().num_weak_senders += 1;
+
+        WeakSender {
+            data: self.data.clone(),
+        }
+    }
+
+    pub fn strong_count(&self) -> usize {
+        self.data.lock().unwrap().num_senders
+    }
+
+    pub fn weak_count(&self) -> usize {
+        self.data.lock().unwrap().num_weak_senders
+    }
+
+    /// Completes when every receiver has been dropped
+    pub async fn closed(&self) {
+        ClosedFuture {
+            data: self.data.clone(),
+        }
+        .await
+    }
+
+    /// Test-side view of the channel
+    pub fn probe(&self) -> ChannelProbe<T> {
+        ChannelProbe {
+            data: self.data.clone(),
+        }
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.data.lock().unwrap().num_senders += 1;
+
+        Self {
+            data: self.data.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        self.data.lock().unwrap().remove_sender();
+    }
+}
+
+impl<T> fmt::Debug for Sender<T> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.debug_struct("Sender").finish()
+    }
+}
+
+/// Sender that does not count toward the senders keeping the channel open
+pub struct WeakSender<T> {
+    data: Arc<Mutex<ChannelData<T>>>,
+}
+
+impl<T> WeakSender<T> {
+    /// Returns a sender if at least one other sender is still alive
+    pub fn upgrade(&self) -> Option<Sender<T>> {
+        if self.data.lock().unwrap().upgrade() {
+            Some(Sender {
+                data: self.data.clone(),
+            })
+        } else {
+            None
+        }
+    }
+
+    pub fn strong_count(&self) -> usize {
+        self.data.lock().unwrap().num_senders
+    }
+
+    pub fn weak_count(&self) -> usize {
+        self.data.lock().unwrap().num_weak_senders
+    }
+}
+
+impl<T> Clone for WeakSender<T> {
+    fn clone(&self) -> Self {
+        self.data.lock().unwrap().num_weak_senders += 1;
+
+        Self {
+            data: self.data.clone(),
+        }
+    }
+}
+
+impl<T> Drop for WeakSender<T> {
+    fn drop(&mut self) {
+        self.data.lock().unwrap().num_weak_senders -= 1;
+    }
+}
+
+impl<T> fmt::Debug for WeakSender<T> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.debug_struct("WeakSender").finish()
+    }
+}
+
+struct ClosedFuture<T> {
+    data: Arc<Mutex<ChannelData<T>>>,
+}
+
+impl<T> Future for ClosedFuture<T> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.data.lock().unwrap().poll_closed(cx)
+    }
+}
+
+/// Receivers are named after the place they subscribed, see [`Receiver::set_name`]
+pub struct Receiver<T> {
+    data: Arc<Mutex<ChannelData<T>>>,
+    id: u64,
+}
+
+impl<T> Receiver<T> {
+    fn new(data: Arc<Mutex<ChannelData<T>>>, location: &'static Location<'static>) -> Self {
+        let id = data.lock().unwrap().subscribe(location);
+        Self { data, id }
+    }
+
+    /// Name under which the probe reports this receiver
+    pub fn set_name(&mut self, name: &str) {
+        let mut data = self.data.lock().unwrap();
+        data.receivers.get_mut(&self.id).unwrap().name = name.to_string();
+    }
+
+    /// Number of messages not received yet, including those lost to lag until a receive
+    /// reports it
+    pub fn len(&self) -> usize {
+        self.data.lock().unwrap().unread(self.id)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn same_channel(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.data, &other.data)
+    }
+
+    pub fn sender_strong_count(&self) -> usize {
+        self.data.lock().unwrap().num_senders
+    }
+
+    pub fn sender_weak_count(&self) -> usize {
+        self.data.lock().unwrap().num_weak_senders
+    }
+
+    /// Returns true once every sender is dropped
+    pub fn is_closed(&self) -> bool {
+        self.data.lock().unwrap().num_senders == 0
+    }
+
+    /// Test-side view of the channel
+    pub fn probe(&self) -> ChannelProbe<T> {
+        ChannelProbe {
+            data: self.data.clone(),
+        }
+    }
+}
+
+impl<T: Clone> Receiver<T> {
+    pub async fn recv(&mut self) -> Result<T, RecvError> {
+        std::future::poll_fn(|cx| self.data.lock().unwrap().poll_recv(self.id, cx)).await
+    }
+
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        self.data.lock().unwrap().try_recv(self.id)
+    }
+
+    /// Blocks the current thread until a message arrives, the receiver lags or every sender
+    /// is dropped
+    pub fn blocking_recv(&mut self) -> Result<T, RecvError> {
+        let data = self.data.lock().unwrap();
+        let blocked = data.blocked.clone();
+        wait_for(data, &blocked, |data| match data.try_recv(self.id) {
+            Ok(value) => Some(Ok(value)),
+            Err(TryRecvError::Lagged(missed)) => Some(Err(RecvError::Lagged(missed))),
+            Err(TryRecvError::Closed) => Some(Err(RecvError::Closed)),
+            Err(TryRecvError::Empty) => None,
+        })
+    }
+
+    /// New receiver starting with the next message sent
+    #[track_caller]
+    pub fn resubscribe(&self) -> Self {
+        Self::new(self.data.clone(), Location::caller())
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.data.lock().unwrap().unsubscribe(self.id);
+    }
+}
+
+impl<T> fmt::Debug for Receiver<T> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.debug_struct("Receiver").finish()
+    }
+}
+
+/// A receiver of the channel as seen by the probe
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Subscriber {
+    name: String,
+    position: u64,
+    unread: usize,
+}
+
+impl Subscriber {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Number of messages sent on the channel before the next one this receiver will get
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// Number of messages sent that this receiver hasn't received yet
+    pub fn unread(&self) -> usize {
+        self.unread
+    }
+}
+
+impl fmt::Display for Subscriber {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} at {} ({} unread)",
+            self.name, self.position, self.unread
+        )
+    }
+}
+
+/// Inspects and manipulates a broadcast channel from the test
+pub struct ChannelProbe<T> {
+    data: Arc<Mutex<ChannelData<T>>>,
+}
+
+impl<T> ChannelProbe<T> {
+    /// The receivers in the order they subscribed
+    pub fn subscribers(&self) -> Vec<Subscriber> {
+        let data = self.data.lock().unwrap();
+        data.receivers
+            .values()
+            .map(|receiver| Subscriber {
+                name: receiver.name.clone(),
+                position: receiver.next,
+                unread: receiver.unread(data.tail),
+            })
+            .collect()
+    }
+
+    /// Make the receivers named `name` lose their `count` oldest unread messages. Their next
+    /// receive returns `Lagged` with the number of messages lost. Returns the number of messages
+    /// skipped, fewer than `count` if not enough were unread.
+    pub fn lag(&self, name: &str, count: u64) -> u64 {
+        let mut data = self.data.lock().unwrap();
+        if !data
+            .receivers
+            .values()
+            .any(|receiver| receiver.name == name)
+        {
+            drop(data);
+            panic!("no receiver named {}", name);
+        }
+        data.lag(name, count)
+    }
+
+    /// Number of messages sent since the channel was created
+    pub fn sent(&self) -> u64 {
+        self.data.lock().unwrap().tail
+    }
+
+    pub fn num_senders(&self) -> usize {
+        self.data.lock().unwrap().num_senders
+    }
+}
+
+impl<T> Clone for ChannelProbe<T> {
+    fn clone(&self) -> Self {
+        Self {
+            data: self.data.clone(),
+        }
+    }
+}
+
+impl<T> fmt::Debug for ChannelProbe<T> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.debug_struct("ChannelProbe")
+            .field("subscribers", &self.subscribers())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::test::*;
+
+    #[test]
+    fn send_wakes_every_receiver() {
+        let (tx, mut rx1) = channel::<u32>(4);
+        let mut rx2 = tx.subscribe();
+
+        let mut recv1 = spawn(rx1.recv());
+        let mut recv2 = spawn(rx2.recv());
+        assert_pending!(recv1.poll());
+        assert_pending!(recv2.poll());
+
+        assert_eq!(tx.send(1).ok(), Some(2));
+        assert!(recv1.is_woken());
+        assert!(recv2.is_woken());
+        assert_ready_eq!(recv1.poll(), Ok(1));
+        assert_ready_eq!(recv2.poll(), Ok(1));
+    }
+
+    #[test]
+    fn dropping_senders_closes() {
+        let (tx, mut rx) = channel::<u32>(4);
+        let mut recv = spawn(rx.recv());
+
+        assert_pending!(recv.poll());
+        drop(tx);
+        assert!(recv.is_woken());
+        assert_ready_eq!(recv.poll(), Err(RecvError::Closed));
+    }
+
+    #[test]
+    fn probe_lists_subscribers_and_positions() {
+        let (tx, mut rx1) = channel::<u32>(4);
+        rx1.set_name("fast");
+        let (rx2, subscribed_at) = (tx.subscribe(), line!());
+        let probe = tx.probe();
+
+        tx.send(1).unwrap();
+        tx.send(2).unwrap();
+        assert_eq!(rx1.try_recv(), Ok(1));
+
+        let subscribers = probe.subscribers();
+        assert_eq!(subscribers.len(), 2);
+        assert_eq!(subscribers[0].to_string(), "fast at 1 (1 unread)");
+        assert_eq!(
+            subscribers[1].name(),
+            format!("{}:{}", file!(), subscribed_at)
+        );
+        assert_eq!(subscribers[1].position(), 0);
+        assert_eq!(subscribers[1].unread(), rx2.len());
+        assert_eq!(probe.sent(), 2);
+    }
+
+    #[test]
+    fn probe_forces_lag() {
+        let (tx, mut rx) = channel::<u32>(16);
+        rx.set_name("slow");
+        let mut other = tx.subscribe();
+        let probe = rx.probe();
+
+        for value in 1..=5 {
+            tx.send(value).unwrap();
+        }
+        assert_eq!(probe.lag("slow", 3), 3);
+        assert_eq!(rx.len(), 5);
+        assert_eq!(probe.subscribers()[0].unread(), 5);
+
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Lagged(3)));
+        assert_eq!(rx.len(), 2);
+        assert_eq!(rx.try_recv(), Ok(4));
+        assert_eq!(other.try_recv(), Ok(1));
+        // fewer messages than asked for are unread
+        assert_eq!(probe.lag("slow", 3), 1);
+        assert_ready_eq!(spawn(rx.recv()).poll(), Err(RecvError::Lagged(1)));
+    }
+
+    #[test]
+    #[should_panic(expected = "no receiver named missing")]
+    fn lagging_unknown_receiver_panics() {
+        let (tx, _rx) = channel::<u32>(1);
+
+        tx.probe().lag("missing", 1);
+    }
+
+    #[test]
+    fn blocking_recv_until_closed() {
+        let (tx, mut rx) = channel::<u32>(4);
+
+        let thread = std::thread::spawn(move || (rx.blocking_recv(), rx.blocking_recv()));
+
+        tx.send(1).unwrap();
+        drop(tx);
+        assert_eq!(thread.join().unwrap(), (Ok(1), Err(RecvError::Closed)));
+    }
+
+    #[test]
+    fn repolling_closed_keeps_one_waker() {
+        let (tx, _rx) = channel::<u32>(1);
+        let mut closed_task = spawn(tx.closed());
+
+        for _ in 0..1000 {
+            assert_pending!(closed_task.poll());
+        }
+        assert_eq!(closed_task.waker_ref_count(), 2);
+    }
+
+    #[test]
+    fn overflow_matches_tokio() {
+        let results = assert_parity!(broadcast, {
+            let (tx, mut rx) = broadcast::channel::<u32>(3);
+            let mut late = tx.subscribe();
+            let mut counts = Vec::new();
+            for value in 1..=6 {
+                counts.push(tx.send(value).map_err(|err| err.0));
+            }
+            let lengths = (tx.len(), rx.len(), late.len());
+            let received: Vec<_> = (0..4).map(|_| rx.try_recv()).collect();
+            let resubscribed = late.resubscribe();
+            let lagged = late.try_recv();
+            drop(late);
+            drop(resubscribed);
+            let after_drop = (tx.len(), tx.receiver_count());
+            drop(tx);
+            let closed = (rx.try_recv(), rx.try_recv(), rx.is_closed());
+            (counts, lengths, received, lagged, after_drop, closed)
+        });
+
+        assert_eq!(results.3, Err(TryRecvError::Lagged(2)));
+    }
+
+    #[test]
+    fn sender_handles_match_tokio() {
+        let results = assert_parity!(broadcast, {
+            let tx = broadcast::Sender::<u32>::new(2);
+            let without_receiver = tx.send(1).map_err(|err| err.0);
+            let mut rx = tx.subscribe();
+            let weak = tx.downgrade();
+            let counts = (
+                tx.strong_count(),
+                tx.weak_count(),
+                rx.sender_strong_count(),
+                rx.sender_weak_count(),
+            );
+            let upgraded = weak.upgrade().map(|tx| tx.send(2).is_ok());
+            drop(tx);
+            let closed = (
+                weak.upgrade().is_none(),
+                weak.strong_count(),
+                rx.is_closed(),
+            );
+            (
+                without_receiver,
+                counts,
+                upgraded,
+                closed,
+                rx.try_recv(),
+                rx.try_recv(),
+            )
+        });
+
+        assert_eq!(results.1, (1, 1, 1, 1));
+        assert_eq!(results.4, Ok(2));
+    }
+}
@@ -2,5 +2,29 @@ pub mod broadcast;
 pub mod mpsc;
 pub mod oneshot;
 
+use std::sync::{Condvar, MutexGuard};
+use std::task::Waker;
+
 // It looks ok to just use tokio's notify
 pub use tokio::sync::Notify;
+
+// a task polled again while still waiting keeps a single entry
+fn register(wakers: &mut Vec<Waker>, waker: &Waker) {
+    if !wakers.iter().any(|existing| existing.will_wake(waker)) {
+        wakers.push(waker.clone());
+    }
+}
+
+// blocks the thread on `blocked` until `ready` gives a result, for the blocking_* methods
+fn wait_for<D, R>(
+    mut data: MutexGuard<'_, D>,
+    blocked: &Condvar,
+    mut ready: impl FnMut(&mut D) -> Option<R>,
+) -> R {
+    loop {
+        if let Some(result) = ready(&mut data) {
+            return result;
+        }
+        data = blocked.wait(data).unwrap();
+    }
+}
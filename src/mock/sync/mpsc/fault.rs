@@ -0,0 +1,345 @@
+use crate::mock::time::{Clock, Instant};
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// Misbehavior applied to the messages sent on a mock channel.
+///
+/// Messages are counted from 1, starting with the first message sent after the policy is
+/// installed. The random faults are drawn from a generator seeded with `seed`, so a given
+/// policy and sequence of sends always misbehaves the same way.
+pub struct FaultPolicy<T> {
+    seed: u64,
+    drop_nth: BTreeSet<usize>,
+    drop_probability: f64,
+    hold_nth: BTreeMap<usize, Instant>,
+    duplicate_probability: f64,
+    duplicator: Option<fn(&T) -> T>,
+    swap_probability: f64,
+}
+
+impl<T> FaultPolicy<T> {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            drop_nth: BTreeSet::new(),
+            drop_probability: 0.0,
+            hold_nth: BTreeMap::new(),
+            duplicate_probability: 0.0,
+            duplicator: None,
+            swap_probability: 0.0,
+        }
+    }
+
+    /// Lose the nth message
+    pub fn drop_nth(mut self, n: usize) -> Self {
+        self.drop_nth.insert(n);
+        self
+    }
+
+    /// Lose each message with the given probability
+    pub fn drop_probability(mut self, probability: f64) -> Self {
+        self.drop_probability = probability;
+        self
+    }
+
+    /// Keep the nth message away from the receiver until the mock clock reaches `until`.
+    /// Messages sent in the meantime overtake it.
+    pub fn hold_nth(mut self, n: usize, until: Instant) -> Self {
+        self.hold_nth.insert(n, until);
+        self
+    }
+
+    /// Deliver each message twice with the given probability. The copy doesn't take a slot of
+    /// its own: it can push a bounded queue past its capacity, and senders then wait until the
+    /// queue drains back below it.
+    pub fn duplicate(mut self, probability: f64) -> Self
+    where
+        T: Clone,
+    {
+        self.duplicate_probability = probability;
+        self.duplicator = Some(T::clone);
+        self
+    }
+
+    /// With the given probability, deliver a message after the one that follows it
+    pub fn swap_adjacent(mut self, probability: f64) -> Self {
+        self.swap_probability = probability;
+        self
+    }
+}
+
+impl<T> fmt::Debug for FaultPolicy<T> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.debug_struct("FaultPolicy")
+            .field("seed", &self.seed)
+            .field("drop_nth", &self.drop_nth)
+            .field("drop_probability", &self.drop_probability)
+            .field("hold_nth", &self.hold_nth)
+            .field("duplicate_probability", &self.duplicate_probability)
+            .field("swap_probability", &self.swap_probability)
+            .finish()
+    }
+}
+
+// splitmix64, small and reproducible on every platform
+struct Rng(u64);
+
+impl Rng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    fn chance(&mut self, probability: f64) -> bool {
+        if probability <= 0.0 {
+            return false;
+        }
+        // 53 random bits give a uniform value in [0, 1)
+        let sample = (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64;
+        sample < probability
+    }
+}
+
+// held messages are numbered across every policy, so a release still scheduled by a
+// replaced policy can't deliver a message held by the new one
+static NEXT_HELD_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Delivers a held message back to the channel, called from the mock clock
+pub(super) type Release = Arc<dyn Fn(u64) + Send + Sync>;
+
+/// A policy installed on a channel, along with the messages it keeps in flight
+pub(super) struct Faults<T> {
+    policy: FaultPolicy<T>,
+    rng: Rng,
+    count: usize,
+    // waiting to be delivered after the next message
+    swapped: Option<T>,
+    held: BTreeMap<u64, T>,
+    clock: Clock,
+    release: Release,
+}
+
+impl<T> Faults<T> {
+    pub(super) fn new(policy: FaultPolicy<T>, release: Release) -> Self {
+        Self {
+            rng: Rng(policy.seed),
+            policy,
+            count: 0,
+            swapped: None,
+            held: BTreeMap::new(),
+            clock: Clock::current(),
+            release,
+        }
+    }
+
+    /// Returns the messages to deliver now, in order
+    pub(super) fn apply(&mut self, value: T) -> Vec<T> {
+        self.count += 1;
+
+        if self.policy.drop_nth.contains(&self.count)
+            || self.rng.chance(self.policy.drop_probability)
+        {
+            return Vec::new();
+        }
+
+        if let Some(until) = self.policy.hold_nth.get(&self.count).copied() {
+            if until > self.clock.now() {
+                self.hold(value, until);
+                return Vec::new();
+            }
+        }
+
+        let mut copies = Vec::with_capacity(2);
+        if let Some(duplicator) = self.policy.duplicator {
+            if self.rng.chance(self.policy.duplicate_probability) {
+                copies.push(duplicator(&value));
+            }
+        }
+        copies.push(value);
+
+        let mut ready = Vec::new();
+        for value in copies {
+            if let Some(swapped) = self.swapped.take() {
+                ready.push(value);
+                ready.push(swapped);
+            } else if self.rng.chance(self.policy.swap_probability) {
+                self.swapped = Some(value);
+            } else {
+                ready.push(value);
+            }
+        }
+        ready
+    }
+
+    fn hold(&mut self, value: T, until: Instant) {
+        let id = NEXT_HELD_ID.fetch_add(1, Ordering::Relaxed);
+        self.held.insert(id, value);
+
+        let release = self.release.clone();
+        self.clock.schedule_at(until, move || release(id));
+    }
+
+    pub(super) fn take_held(&mut self, id: u64) -> Option<T> {
+        self.held.remove(&id)
+    }
+
+    /// The message waiting for a swap partner, delivered as is once no other message can come
+    pub(super) fn take_swapped(&mut self) -> Option<T> {
+        self.swapped.take()
+    }
+
+    /// Messages that were sent but not delivered yet
+    pub(super) fn take_in_flight(&mut self) -> Vec<T> {
+        let held = std::mem::take(&mut self.held);
+        self.swapped
+            .take()
+            .into_iter()
+            .chain(held.into_values())
+            .collect()
+    }
+
+    pub(super) fn is_idle(&self) -> bool {
+        self.swapped.is_none() && self.held.is_empty()
+    }
+}
+
+impl<T> fmt::Debug for Faults<T> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.debug_struct("Faults")
+            .field("policy", &self.policy)
+            .field("count", &self.count)
+            .field("held", &self.held.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+    use crate::mock::test::*;
+    use crate::mock::time;
+
+    fn received<T>(rx: &mut Receiver<T>) -> Vec<T> {
+        std::iter::from_fn(|| rx.try_recv().ok()).collect()
+    }
+
+    #[test]
+    fn drops_nth_message() {
+        let (tx, mut rx) = channel_with_faults(8, FaultPolicy::new(0).drop_nth(2));
+
+        for value in 1..=3 {
+            assert!(tx.try_send(value).is_ok());
+        }
+        assert_eq!(received(&mut rx), vec![1, 3]);
+    }
+
+    #[test]
+    fn holds_message_until_instant() {
+        let until = time::Instant::now() + Duration::from_secs(5);
+        let (tx, mut rx) = unbounded_channel_with_faults(FaultPolicy::new(0).hold_nth(1, until));
+
+        let mut recv_task = spawn(rx.recv());
+        tx.send(1).unwrap();
+        tx.send(2).unwrap();
+        drop(tx);
+        assert_ready_eq!(recv_task.poll(), Some(2));
+        drop(recv_task);
+
+        let mut recv_task = spawn(rx.recv());
+        assert_pending!(recv_task.poll());
+        time::advance(Duration::from_secs(4));
+        assert_pending!(recv_task.poll());
+        time::advance(Duration::from_secs(1));
+        assert!(recv_task.is_woken());
+        assert_ready_eq!(recv_task.poll(), Some(1));
+        drop(recv_task);
+        assert_ready_eq!(spawn(rx.recv()).poll(), None);
+    }
+
+    #[test]
+    fn duplicates_and_swaps() {
+        let (tx, mut rx) =
+            unbounded_channel_with_faults(FaultPolicy::new(0).duplicate(1.0).swap_adjacent(0.0));
+
+        tx.send(1).unwrap();
+        assert_eq!(received(&mut rx), vec![1, 1]);
+
+        let probe = rx.probe();
+        probe.set_faults(FaultPolicy::new(0).swap_adjacent(1.0));
+        tx.send(1).unwrap();
+        tx.send(2).unwrap();
+        tx.send(3).unwrap();
+        assert_eq!(received(&mut rx), vec![2, 1]);
+        // the last message has no partner and comes out once the sender is gone
+        drop(tx);
+        assert_eq!(received(&mut rx), vec![3]);
+    }
+
+    #[test]
+    fn same_seed_gives_same_faults() {
+        let run = |seed| {
+            let policy = FaultPolicy::new(seed)
+                .drop_probability(0.2)
+                .duplicate(0.2)
+                .swap_adjacent(0.2);
+            let (tx, mut rx) = unbounded_channel_with_faults(policy);
+            for value in 0..100 {
+                tx.send(value).unwrap();
+            }
+            drop(tx);
+            received(&mut rx)
+        };
+
+        assert_eq!(run(7), run(7));
+        assert_ne!(run(7), run(8));
+        assert_ne!(run(7), (0..100).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn duplicates_can_exceed_capacity() {
+        let (tx, mut rx) = channel_with_faults(1, FaultPolicy::new(0).duplicate(1.0));
+
+        assert!(tx.try_send(1).is_ok());
+        assert_eq!(tx.capacity(), 0);
+        assert!(matches!(tx.try_send(2), Err(TrySendError::Full(2))));
+        assert_eq!(rx.try_recv(), Ok(1));
+        assert_eq!(tx.capacity(), 0);
+        assert_eq!(rx.try_recv(), Ok(1));
+        assert_eq!(tx.capacity(), 1);
+    }
+
+    #[test]
+    fn replaced_policy_does_not_release_new_holds() {
+        let start = time::Instant::now();
+        let policy = FaultPolicy::new(0).hold_nth(1, start + Duration::from_secs(10));
+        let (tx, mut rx) = unbounded_channel_with_faults(policy);
+
+        tx.send(1).unwrap();
+        rx.probe()
+            .set_faults(FaultPolicy::new(0).hold_nth(1, start + Duration::from_secs(100)));
+        tx.send(2).unwrap();
+        assert_eq!(received(&mut rx), vec![1]);
+
+        time::advance(Duration::from_secs(10));
+        assert_eq!(received(&mut rx), vec![]);
+        time::advance(Duration::from_secs(90));
+        assert_eq!(received(&mut rx), vec![2]);
+    }
+
+    #[test]
+    fn clearing_faults_delivers_messages_in_flight() {
+        let until = time::Instant::now() + Duration::from_secs(5);
+        let (tx, mut rx) = channel_with_faults(4, FaultPolicy::new(0).hold_nth(1, until));
+
+        assert!(tx.try_send(1).is_ok());
+        assert!(rx.try_recv().is_err());
+        rx.probe().clear_faults();
+        assert_eq!(rx.try_recv(), Ok(1));
+    }
+}
@@ -0,0 +1,2005 @@
+use std::any::Any;
+use std::collections::VecDeque;
+use std::fmt;
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+
+use tokio::sync::mpsc::error::{SendError, SendTimeoutError, TryRecvError, TrySendError};
+
+pub use tokio::sync::mpsc::error;
+
+mod fault;
+
+pub use fault::FaultPolicy;
+
+use fault::Faults;
+
+use super::{register, wait_for};
+
+/// Takes over the messages sent on a channel, used by `mock::test::scripted`
+pub(crate) trait Intercept<T>: Send {
+    /// Returns a description of the failure if the message was not expected
+    fn intercept(&mut self, value: T) -> Result<(), String>;
+}
+
+impl<T> fmt::Debug for dyn Intercept<T> + '_ {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.debug_struct("Intercept").finish()
+    }
+}
+
+#[derive(Debug)]
+struct ChannelData<T> {
+    queue: VecDeque<T>,
+    max_size: Option<usize>,
+    num_senders: usize,
+    // slots held by permits, they count against the capacity
+    reserved: usize,
+    // longest the queue has been, reported by the probe
+    high_water_mark: usize,
+    is_active: bool,
+    recv_waker: Option<Waker>,
+    send_wakers: Vec<Waker>,
+    closed_wakers: Vec<Waker>,
+    // notified alongside the wakers for threads in blocking_send / blocking_recv
+    blocked: Arc<Condvar>,
+    // runs once the lock is released, so that the intercept may use the channel
+    intercept: Option<Arc<Mutex<Box<dyn Intercept<T>>>>>,
+    // messages sent while the lock was held, waiting for the intercept
+    intercepted: VecDeque<T>,
+    // failure from the intercept for a message no sender delivered, such as one released
+    // by the clock, left for the owner of the intercept to report
+    rejected: Option<String>,
+    faults: Option<Faults<T>>,
+}
+
+impl<T> ChannelData<T> {
+    fn new(max_size: Option<usize>) -> Self {
+        Self {
+            queue: VecDeque::new(),
+            max_size,
+            num_senders: 1,
+            reserved: 0,
+            high_water_mark: 0,
+            is_active: true,
+            recv_waker: None,
+            send_wakers: Vec::new(),
+            closed_wakers: Vec::new(),
+            blocked: Arc::new(Condvar::new()),
+            intercept: None,
+            intercepted: VecDeque::new(),
+            rejected: None,
+            faults: None,
+        }
+    }
+
+    fn poll_recv(&mut self, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        if let Some(msg) = self.pop() {
+            Poll::Ready(Some(msg))
+        } else if self.is_disconnected() {
+            Poll::Ready(None)
+        } else {
+            self.recv_waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+
+    fn poll_recv_many(
+        &mut self,
+        cx: &mut Context<'_>,
+        buffer: &mut Vec<T>,
+        limit: usize,
+    ) -> Poll<usize> {
+        if limit == 0 {
+            return Poll::Ready(0);
+        }
+
+        let count = limit.min(self.queue.len());
+        if count > 0 {
+            buffer.extend(self.queue.drain(..count));
+            self.wake_senders();
+            Poll::Ready(count)
+        } else if self.is_disconnected() {
+            Poll::Ready(0)
+        } else {
+            self.recv_waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+
+    fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        if let Some(msg) = self.pop() {
+            Ok(msg)
+        } else if self.is_disconnected() {
+            Err(TryRecvError::Disconnected)
+        } else {
+            Err(TryRecvError::Empty)
+        }
+    }
+
+    // no more messages can arrive once the queue is drained
+    fn is_disconnected(&self) -> bool {
+        let is_idle = match self.faults.as_ref() {
+            Some(faults) => faults.is_idle(),
+            None => true,
+        };
+        is_idle && (self.num_senders == 0 || (!self.is_active && self.reserved == 0))
+    }
+
+    fn has_capacity(&self) -> bool {
+        match self.max_size {
+            Some(max_size) => self.queue.len() + self.reserved < max_size,
+            None => true,
+        }
+    }
+
+    fn try_reserve(&mut self) -> Result<(), TrySendError<()>> {
+        if !self.is_active {
+            return Err(TrySendError::Closed(()));
+        }
+
+        if self.has_capacity() {
+            self.reserved += 1;
+            Ok(())
+        } else {
+            Err(TrySendError::Full(()))
+        }
+    }
+
+    fn poll_reserve(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), SendError<()>>> {
+        match self.try_reserve() {
+            Ok(()) => Poll::Ready(Ok(())),
+            Err(TrySendError::Closed(())) => Poll::Ready(Err(SendError(()))),
+            Err(TrySendError::Full(())) => {
+                register(&mut self.send_wakers, cx.waker());
+                Poll::Pending
+            }
+        }
+    }
+
+    fn send_reserved(&mut self, value: T) {
+        self.reserved -= 1;
+        self.push(value);
+    }
+
+    fn release(&mut self) {
+        self.reserved -= 1;
+        self.wake_senders();
+        if self.is_disconnected() {
+            self.wake_receiver();
+        }
+    }
+
+    fn push(&mut self, value: T) {
+        let faults = match self.faults.as_mut() {
+            Some(faults) => faults,
+            None => return self.enqueue(value),
+        };
+
+        let ready = faults.apply(value);
+        if ready.is_empty() {
+            // held back or lost, the slot of a permit it was sent with is free again
+            self.wake_senders();
+        }
+        for value in ready {
+            self.enqueue(value);
+        }
+    }
+
+    fn enqueue(&mut self, value: T) {
+        if self.intercept.is_some() {
+            self.intercepted.push_back(value);
+            // the message never takes a slot, so a permit it was sent with frees capacity
+            self.wake_senders();
+            return;
+        }
+
+        self.queue.push_back(value);
+        self.high_water_mark = self.high_water_mark.max(self.queue.len());
+        self.wake_receiver();
+    }
+
+    fn pop(&mut self) -> Option<T> {
+        let value = self.queue.pop_front();
+        if value.is_some() {
+            self.wake_senders();
+        }
+        value
+    }
+
+    fn close(&mut self) {
+        self.flush_swapped();
+        self.is_active = false;
+        self.wake_senders();
+        for waker in self.closed_wakers.drain(..) {
+            waker.wake();
+        }
+    }
+
+    fn poll_closed(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        if self.is_active {
+            register(&mut self.closed_wakers, cx.waker());
+            Poll::Pending
+        } else {
+            Poll::Ready(())
+        }
+    }
+
+    fn capacity(&self) -> usize {
+        // the probe can queue past the capacity
+        self.max_size.map_or(usize::MAX, |max_size| {
+            max_size.saturating_sub(self.queue.len() + self.reserved)
+        })
+    }
+
+    fn upgrade(&mut self) -> bool {
+        if self.num_senders == 0 {
+            return false;
+        }
+        self.add_sender();
+        true
+    }
+
+    fn add_sender(&mut self) {
+        self.num_senders = self.num_senders.saturating_add(1);
+    }
+
+    fn remove_sender(&mut self) {
+        self.num_senders = self.num_senders.saturating_sub(1);
+        if self.num_senders == 0 {
+            self.flush_swapped();
+            self.wake_receiver();
+        }
+    }
+
+    fn set_faults(&mut self, faults: Option<Faults<T>>) {
+        let in_flight = match self.faults.as_mut() {
+            Some(faults) => faults.take_in_flight(),
+            None => Vec::new(),
+        };
+        self.faults = faults;
+        for value in in_flight {
+            self.enqueue(value);
+        }
+        // an empty channel may have just become disconnected
+        self.wake_receiver();
+    }
+
+    fn release_held(&mut self, id: u64) {
+        if let Some(value) = self.faults.as_mut().and_then(|faults| faults.take_held(id)) {
+            self.enqueue(value);
+        }
+        self.wake_receiver();
+    }
+
+    // a message kept back to be swapped is delivered once no other message can follow it
+    fn flush_swapped(&mut self) {
+        if let Some(value) = self.faults.as_mut().and_then(Faults::take_swapped) {
+            self.enqueue(value);
+        }
+    }
+
+    fn wake_receiver(&mut self) {
+        if let Some(waker) = self.recv_waker.take() {
+            waker.wake();
+        }
+        self.blocked.notify_all();
+    }
+
+    fn wake_senders(&mut self) {
+        for waker in self.send_wakers.drain(..) {
+            waker.wake();
+        }
+        self.blocked.notify_all();
+    }
+
+    fn try_send(&mut self, value: T) -> Result<(), TrySendError<T>> {
+        if !self.is_active {
+            return Err(TrySendError::Closed(value));
+        }
+
+        if self.has_capacity() {
+            self.push(value);
+            Ok(())
+        } else {
+            Err(TrySendError::Full(value))
+        }
+    }
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => match payload.downcast::<&'static str>() {
+            Ok(message) => message.to_string(),
+            Err(_) => "intercept panicked".to_string(),
+        },
+    }
+}
+
+// hands the messages sent while the lock was held to the intercept, in order, once the lock
+// is released, and returns the first failure
+fn deliver<'a, T>(
+    channel: &'a Mutex<ChannelData<T>>,
+    mut data: MutexGuard<'a, ChannelData<T>>,
+) -> Option<String> {
+    let mut rejected = None;
+    loop {
+        let intercept = match &data.intercept {
+            Some(intercept) if !data.intercepted.is_empty() => intercept.clone(),
+            _ => return rejected,
+        };
+        drop(data);
+
+        // a thread already running the intercept, this one if the intercept sends on the
+        // channel, delivers these messages after its own
+        let mut intercept = match intercept.try_lock() {
+            Ok(intercept) => intercept,
+            Err(_) => return rejected,
+        };
+        loop {
+            let value = match channel.lock().unwrap().intercepted.pop_front() {
+                Some(value) => value,
+                None => break,
+            };
+            // a panicking check is reported like a rejection instead of poisoning the channel
+            let result = panic::catch_unwind(AssertUnwindSafe(|| intercept.intercept(value)))
+                .unwrap_or_else(|payload| Err(panic_message(payload)));
+            if let Err(err) = result {
+                rejected.get_or_insert(err);
+            }
+        }
+        drop(intercept);
+
+        // messages sent just before the intercept was unlocked found it busy
+        data = channel.lock().unwrap();
+    }
+}
+
+// panics with a failure from the intercept after unlocking, so the channel isn't poisoned
+fn check_rejected<T>(channel: &Mutex<ChannelData<T>>, data: MutexGuard<'_, ChannelData<T>>) {
+    if let Some(err) = deliver(channel, data) {
+        panic!("{}", err);
+    }
+}
+
+// no sender is around to report a failure, the owner of the intercept does it later
+fn keep_rejected<T>(channel: &Mutex<ChannelData<T>>, data: MutexGuard<'_, ChannelData<T>>) {
+    if let Some(err) = deliver(channel, data) {
+        channel.lock().unwrap().rejected.get_or_insert(err);
+    }
+}
+
+struct ReceiveFuture<T> {
+    data: Arc<Mutex<ChannelData<T>>>,
+}
+
+impl<T> Future for ReceiveFuture<T> {
+    type Output = Option<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.data.lock().unwrap().poll_recv(cx)
+    }
+}
+
+struct ReserveFuture<T> {
+    data: Arc<Mutex<ChannelData<T>>>,
+}
+
+impl<T> Future for ReserveFuture<T> {
+    type Output = Result<(), SendError<()>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.data.lock().unwrap().poll_reserve(cx)
+    }
+}
+
+struct ClosedFuture<T> {
+    data: Arc<Mutex<ChannelData<T>>>,
+}
+
+impl<T> Future for ClosedFuture<T> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.data.lock().unwrap().poll_closed(cx)
+    }
+}
+
+pub struct Receiver<T> {
+    data: Arc<Mutex<ChannelData<T>>>,
+}
+
+impl<T> Receiver<T> {
+    fn new(data: Arc<Mutex<ChannelData<T>>>) -> Self {
+        Self { data }
+    }
+
+    pub fn recv(&mut self) -> impl Future<Output = Option<T>> {
+        ReceiveFuture {
+            data: self.data.clone(),
+        }
+    }
+
+    pub fn poll_recv(&mut self, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        self.data.lock().unwrap().poll_recv(cx)
+    }
+
+    pub async fn recv_many(&mut self, buffer: &mut Vec<T>, limit: usize) -> usize {
+        std::future::poll_fn(|cx| self.poll_recv_many(cx, buffer, limit)).await
+    }
+
+    pub fn poll_recv_many(
+        &mut self,
+        cx: &mut Context<'_>,
+        buffer: &mut Vec<T>,
+        limit: usize,
+    ) -> Poll<usize> {
+        self.data.lock().unwrap().poll_recv_many(cx, buffer, limit)
+    }
+
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        self.data.lock().unwrap().try_recv()
+    }
+
+    /// Blocks the current thread until a message arrives or the channel is disconnected
+    pub fn blocking_recv(&mut self) -> Option<T> {
+        let data = self.data.lock().unwrap();
+        let blocked = data.blocked.clone();
+        wait_for(data, &blocked, |data| match data.try_recv() {
+            Ok(msg) => Some(Some(msg)),
+            Err(TryRecvError::Disconnected) => Some(None),
+            Err(TryRecvError::Empty) => None,
+        })
+    }
+
+    pub fn close(&mut self) {
+        let mut data = self.data.lock().unwrap();
+        data.close();
+        keep_rejected(&self.data, data);
+    }
+
+    /// Test-side view of the channel
+    pub fn probe(&self) -> ChannelProbe<T> {
+        ChannelProbe::new(self.data.clone())
+    }
+
+    /// Diverts every message to the intercept, starting with those already queued
+    pub(crate) fn intercept(&self, intercept: Box<dyn Intercept<T>>) {
+        let mut data = self.data.lock().unwrap();
+        data.intercept = Some(Arc::new(Mutex::new(intercept)));
+        let queued: Vec<T> = data.queue.drain(..).collect();
+        for value in queued {
+            data.enqueue(value);
+        }
+        check_rejected(&self.data, data);
+    }
+
+    /// A failure from the intercept no sender has reported, such as one for a message
+    /// released by the clock or flushed on close
+    pub(crate) fn take_rejected(&self) -> Option<String> {
+        self.data.lock().unwrap().rejected.take()
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+impl<T> fmt::Debug for Receiver<T> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.debug_struct("Receiver").finish()
+    }
+}
+
+pub struct Sender<T> {
+    data: Arc<Mutex<ChannelData<T>>>,
+    // slot reserved by poll_ready when used as a Sink
+    #[cfg(feature = "futures")]
+    sink_permit: bool,
+}
+
+impl<T> Sender<T> {
+    fn new(data: Arc<Mutex<ChannelData<T>>>) -> Self {
+        Self {
+            data,
+            #[cfg(feature = "futures")]
+            sink_permit: false,
+        }
+    }
+
+    pub async fn send(&self, value: T) -> Result<(), SendError<T>> {
+        match self.reserve().await {
+            Ok(permit) => {
+                permit.send(value);
+                Ok(())
+            }
+            Err(SendError(())) => Err(SendError(value)),
+        }
+    }
+
+    pub fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+        let mut data = self.data.lock().unwrap();
+        let result = data.try_send(value);
+        check_rejected(&self.data, data);
+        result
+    }
+
+    /// Waits for capacity until the timeout elapses on the mock clock
+    pub async fn send_timeout(
+        &self,
+        value: T,
+        timeout: Duration,
+    ) -> Result<(), SendTimeoutError<T>> {
+        match crate::mock::time::timeout(timeout, self.reserve()).await {
+            Ok(Ok(permit)) => {
+                permit.send(value);
+                Ok(())
+            }
+            Ok(Err(SendError(()))) => Err(SendTimeoutError::Closed(value)),
+            Err(_) => Err(SendTimeoutError::Timeout(value)),
+        }
+    }
+
+    /// Blocks the current thread until there is capacity for the value
+    pub fn blocking_send(&self, value: T) -> Result<(), SendError<T>> {
+        let mut data = self.data.lock().unwrap();
+        let blocked = data.blocked.clone();
+        let mut value = value;
+        let result = loop {
+            match data.try_send(value) {
+                Ok(()) => break Ok(()),
+                Err(TrySendError::Closed(value)) => break Err(SendError(value)),
+                Err(TrySendError::Full(full)) => {
+                    value = full;
+                    data = blocked.wait(data).unwrap();
+                }
+            }
+        };
+        check_rejected(&self.data, data);
+        result
+    }
+
+    pub async fn reserve(&self) -> Result<Permit<'_, T>, SendError<()>> {
+        ReserveFuture {
+            data: self.data.clone(),
+        }
+        .await?;
+
+        Ok(Permit { sender: self })
+    }
+
+    pub fn try_reserve(&self) -> Result<Permit<'_, T>, TrySendError<()>> {
+        self.data.lock().unwrap().try_reserve()?;
+
+        Ok(Permit { sender: self })
+    }
+
+    pub async fn reserve_owned(self) -> Result<OwnedPermit<T>, SendError<()>> {
+        ReserveFuture {
+            data: self.data.clone(),
+        }
+        .await?;
+
+        Ok(OwnedPermit { sender: Some(self) })
+    }
+
+    pub fn try_reserve_owned(self) -> Result<OwnedPermit<T>, TrySendError<Self>> {
+        let result = self.data.lock().unwrap().try_reserve();
+
+        match result {
+            Ok(()) => Ok(OwnedPermit { sender: Some(self) }),
+            Err(TrySendError::Full(())) => Err(TrySendError::Full(self)),
+            Err(TrySendError::Closed(())) => Err(TrySendError::Closed(self)),
+        }
+    }
+
+    /// Completes when the receiver is closed or dropped
+    pub async fn closed(&self) {
+        ClosedFuture {
+            data: self.data.clone(),
+        }
+        .await
+    }
+
+    pub fn is_closed(&self) -> bool {
+        !self.data.lock().unwrap().is_active
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.data.lock().unwrap().capacity()
+    }
+
+    pub fn max_capacity(&self) -> usize {
+        self.data.lock().unwrap().max_size.unwrap_or(usize::MAX)
+    }
+
+    pub fn same_channel(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.data, &other.data)
+    }
+
+    /// Create a sender that does not keep the channel open
+    pub fn downgrade(&self) -> WeakSender<T> {
+        WeakSender {
+            data: self.data.clone(),
+        }
+    }
+
+    /// Test-side view of the channel
+    pub fn probe(&self) -> ChannelProbe<T> {
+        ChannelProbe::new(self.data.clone())
+    }
+}
+
+/// Sender that does not count toward the senders keeping the channel open
+pub struct WeakSender<T> {
+    data: Arc<Mutex<ChannelData<T>>>,
+}
+
+impl<T> WeakSender<T> {
+    /// Returns a sender if at least one other sender is still alive
+    pub fn upgrade(&self) -> Option<Sender<T>> {
+        if self.data.lock().unwrap().upgrade() {
+            Some(Sender::new(self.data.clone()))
+        } else {
+            None
+        }
+    }
+}
+
+impl<T> Clone for WeakSender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            data: self.data.clone(),
+        }
+    }
+}
+
+impl<T> fmt::Debug for WeakSender<T> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.debug_struct("WeakSender").finish()
+    }
+}
+
+/// Slot reserved in the channel with [`Sender::reserve`]
+pub struct Permit<'a, T> {
+    sender: &'a Sender<T>,
+}
+
+impl<T> Permit<'_, T> {
+    pub fn send(self, value: T) {
+        let sender = self.sender;
+        let mut data = sender.data.lock().unwrap();
+        data.send_reserved(value);
+        std::mem::forget(self);
+        check_rejected(&sender.data, data);
+    }
+}
+
+impl<T> Drop for Permit<'_, T> {
+    fn drop(&mut self) {
+        self.sender.data.lock().unwrap().release();
+    }
+}
+
+impl<T> fmt::Debug for Permit<'_, T> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.debug_struct("Permit").finish()
+    }
+}
+
+/// Slot reserved in the channel with [`Sender::reserve_owned`]
+pub struct OwnedPermit<T> {
+    sender: Option<Sender<T>>,
+}
+
+impl<T> OwnedPermit<T> {
+    pub fn send(mut self, value: T) -> Sender<T> {
+        let sender = self.sender.take().unwrap();
+        let mut data = sender.data.lock().unwrap();
+        data.send_reserved(value);
+        check_rejected(&sender.data, data);
+        sender
+    }
+
+    pub fn release(mut self) -> Sender<T> {
+        let sender = self.sender.take().unwrap();
+        sender.data.lock().unwrap().release();
+        sender
+    }
+}
+
+impl<T> Drop for OwnedPermit<T> {
+    fn drop(&mut self) {
+        if let Some(sender) = self.sender.take() {
+            sender.data.lock().unwrap().release();
+        }
+    }
+}
+
+impl<T> fmt::Debug for OwnedPermit<T> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.debug_struct("OwnedPermit").finish()
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        #[cfg(feature = "futures")]
+        self.release_sink_permit();
+        let mut data = self.data.lock().unwrap();
+        data.remove_sender();
+        // the last sender flushes a message kept back by the fault policy
+        if !std::thread::panicking() {
+            check_rejected(&self.data, data);
+        }
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.data.lock().unwrap().add_sender();
+
+        Self::new(self.data.clone())
+    }
+}
+
+impl<T> fmt::Debug for Sender<T> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.debug_struct("Sender").finish()
+    }
+}
+
+pub type UnboundedReceiver<T> = Receiver<T>;
+
+pub struct UnboundedSender<T> {
+    data: Arc<Mutex<ChannelData<T>>>,
+}
+
+impl<T> UnboundedSender<T> {
+    fn new(data: Arc<Mutex<ChannelData<T>>>) -> Self {
+        Self { data }
+    }
+
+    pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+        let mut data = self.data.lock().unwrap();
+
+        if data.is_active {
+            data.push(value);
+            check_rejected(&self.data, data);
+            Ok(())
+        } else {
+            Err(SendError(value))
+        }
+    }
+
+    pub fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+        let mut data = self.data.lock().unwrap();
+        let result = data.try_send(value);
+        check_rejected(&self.data, data);
+        result
+    }
+
+    /// Completes when the receiver is closed or dropped
+    pub async fn closed(&self) {
+        ClosedFuture {
+            data: self.data.clone(),
+        }
+        .await
+    }
+
+    pub fn is_closed(&self) -> bool {
+        !self.data.lock().unwrap().is_active
+    }
+
+    pub fn same_channel(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.data, &other.data)
+    }
+
+    /// Create a sender that does not keep the channel open
+    pub fn downgrade(&self) -> WeakUnboundedSender<T> {
+        WeakUnboundedSender {
+            data: self.data.clone(),
+        }
+    }
+
+    /// Test-side view of the channel
+    pub fn probe(&self) -> ChannelProbe<T> {
+        ChannelProbe::new(self.data.clone())
+    }
+}
+
+/// Sender that does not count toward the senders keeping the channel open
+pub struct WeakUnboundedSender<T> {
+    data: Arc<Mutex<ChannelData<T>>>,
+}
+
+impl<T> WeakUnboundedSender<T> {
+    /// Returns a sender if at least one other sender is still alive
+    pub fn upgrade(&self) -> Option<UnboundedSender<T>> {
+        if self.data.lock().unwrap().upgrade() {
+            Some(UnboundedSender::new(self.data.clone()))
+        } else {
+            None
+        }
+    }
+}
+
+impl<T> Clone for WeakUnboundedSender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            data: self.data.clone(),
+        }
+    }
+}
+
+impl<T> fmt::Debug for WeakUnboundedSender<T> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.debug_struct("WeakUnboundedSender").finish()
+    }
+}
+
+impl<T> Drop for UnboundedSender<T> {
+    fn drop(&mut self) {
+        let mut data = self.data.lock().unwrap();
+        data.remove_sender();
+        if !std::thread::panicking() {
+            check_rejected(&self.data, data);
+        }
+    }
+}
+
+impl<T> Clone for UnboundedSender<T> {
+    fn clone(&self) -> Self {
+        self.data.lock().unwrap().add_sender();
+
+        Self::new(self.data.clone())
+    }
+}
+
+impl<T> fmt::Debug for UnboundedSender<T> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.debug_struct("UnboundedSender").finish()
+    }
+}
+
+#[cfg(feature = "futures")]
+impl<T> futures_core::Stream for Receiver<T> {
+    type Item = T;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        self.poll_recv(cx)
+    }
+}
+
+#[cfg(feature = "futures")]
+impl<T> Sender<T> {
+    fn poll_sink_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), SendError<()>>> {
+        if !self.sink_permit {
+            std::task::ready!(self.data.lock().unwrap().poll_reserve(cx))?;
+            self.sink_permit = true;
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_sink_send(&mut self, value: T) -> Result<(), T> {
+        if !std::mem::take(&mut self.sink_permit) {
+            return Err(value);
+        }
+
+        let mut data = self.data.lock().unwrap();
+        data.send_reserved(value);
+        check_rejected(&self.data, data);
+        Ok(())
+    }
+
+    fn release_sink_permit(&mut self) -> bool {
+        let released = std::mem::take(&mut self.sink_permit);
+        if released {
+            self.data.lock().unwrap().release();
+        }
+        released
+    }
+}
+
+/// `start_send` fails unless `poll_ready` reserved a slot first
+#[cfg(feature = "futures")]
+impl<T> futures_sink::Sink<T> for Sender<T> {
+    type Error = SendError<()>;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.get_mut().poll_sink_ready(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: T) -> Result<(), Self::Error> {
+        self.get_mut()
+            .start_sink_send(item)
+            .map_err(|_| SendError(()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.get_mut().release_sink_permit();
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(feature = "futures")]
+impl<T> futures_sink::Sink<T> for UnboundedSender<T> {
+    type Error = SendError<()>;
+
+    fn poll_ready(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        if self.is_closed() {
+            Poll::Ready(Err(SendError(())))
+        } else {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: T) -> Result<(), Self::Error> {
+        self.send(item).map_err(|_| SendError(()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Mock of tokio-stream's `ReceiverStream`
+#[cfg(feature = "futures")]
+#[derive(Debug)]
+pub struct ReceiverStream<T> {
+    inner: Receiver<T>,
+}
+
+#[cfg(feature = "futures")]
+impl<T> ReceiverStream<T> {
+    pub fn new(recv: Receiver<T>) -> Self {
+        Self { inner: recv }
+    }
+
+    pub fn into_inner(self) -> Receiver<T> {
+        self.inner
+    }
+
+    pub fn close(&mut self) {
+        self.inner.close();
+    }
+}
+
+#[cfg(feature = "futures")]
+impl<T> futures_core::Stream for ReceiverStream<T> {
+    type Item = T;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        self.inner.poll_recv(cx)
+    }
+}
+
+#[cfg(feature = "futures")]
+impl<T> AsRef<Receiver<T>> for ReceiverStream<T> {
+    fn as_ref(&self) -> &Receiver<T> {
+        &self.inner
+    }
+}
+
+#[cfg(feature = "futures")]
+impl<T> AsMut<Receiver<T>> for ReceiverStream<T> {
+    fn as_mut(&mut self) -> &mut Receiver<T> {
+        &mut self.inner
+    }
+}
+
+#[cfg(feature = "futures")]
+impl<T> From<Receiver<T>> for ReceiverStream<T> {
+    fn from(recv: Receiver<T>) -> Self {
+        Self::new(recv)
+    }
+}
+
+/// Mock of tokio-stream's `UnboundedReceiverStream`
+#[cfg(feature = "futures")]
+pub type UnboundedReceiverStream<T> = ReceiverStream<T>;
+
+/// Error returned by [`PollSender`] when the channel is closed
+#[cfg(feature = "futures")]
+#[derive(Debug)]
+pub struct PollSendError<T>(Option<T>);
+
+#[cfg(feature = "futures")]
+impl<T> PollSendError<T> {
+    /// The item that could not be sent, if the error came from `send_item`
+    pub fn into_inner(self) -> Option<T> {
+        self.0
+    }
+}
+
+#[cfg(feature = "futures")]
+impl<T> fmt::Display for PollSendError<T> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(fmt, "channel closed")
+    }
+}
+
+#[cfg(feature = "futures")]
+impl<T: fmt::Debug> std::error::Error for PollSendError<T> {}
+
+/// Mock of tokio-util's `PollSender`
+#[cfg(feature = "futures")]
+#[derive(Debug)]
+pub struct PollSender<T> {
+    // dropped once closed, unless it still holds a reserved slot
+    sender: Option<Sender<T>>,
+    is_closed: bool,
+}
+
+#[cfg(feature = "futures")]
+impl<T> PollSender<T> {
+    pub fn new(sender: Sender<T>) -> Self {
+        Self {
+            sender: Some(sender),
+            is_closed: false,
+        }
+    }
+
+    pub fn poll_reserve(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), PollSendError<T>>> {
+        let sender = match self.sender.as_mut() {
+            Some(sender) if sender.sink_permit || !self.is_closed => sender,
+            _ => return Poll::Ready(Err(PollSendError(None))),
+        };
+
+        match sender.poll_sink_ready(cx) {
+            Poll::Ready(Ok(())) => Poll::Ready(Ok(())),
+            Poll::Ready(Err(_)) => {
+                self.sender = None;
+                Poll::Ready(Err(PollSendError(None)))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    /// Panics if `poll_reserve` did not reserve a slot first, like tokio-util
+    #[track_caller]
+    pub fn send_item(&mut self, value: T) -> Result<(), PollSendError<T>> {
+        let sender = match self.sender.as_mut() {
+            Some(sender) => sender,
+            None => return Err(PollSendError(Some(value))),
+        };
+
+        if sender.start_sink_send(value).is_err() {
+            panic!("`send_item` called without first calling `poll_reserve`");
+        }
+
+        if self.is_closed {
+            self.sender = None;
+        }
+        Ok(())
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.is_closed || self.sender.is_none()
+    }
+
+    pub fn get_ref(&self) -> Option<&Sender<T>> {
+        if self.is_closed {
+            None
+        } else {
+            self.sender.as_ref()
+        }
+    }
+
+    /// A slot already reserved can still be used by a final `send_item`
+    pub fn close(&mut self) {
+        self.is_closed = true;
+        if self
+            .sender
+            .as_ref()
+            .is_some_and(|sender| !sender.sink_permit)
+        {
+            self.sender = None;
+        }
+    }
+
+    pub fn abort_send(&mut self) -> bool {
+        let aborted = self
+            .sender
+            .as_mut()
+            .is_some_and(|sender| sender.release_sink_permit());
+        if self.is_closed {
+            self.sender = None;
+        }
+        aborted
+    }
+}
+
+#[cfg(feature = "futures")]
+impl<T> Clone for PollSender<T> {
+    fn clone(&self) -> Self {
+        match self.get_ref() {
+            Some(sender) => Self::new(sender.clone()),
+            None => Self {
+                sender: None,
+                is_closed: true,
+            },
+        }
+    }
+}
+
+#[cfg(feature = "futures")]
+impl<T> futures_sink::Sink<T> for PollSender<T> {
+    type Error = PollSendError<T>;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.get_mut().poll_reserve(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: T) -> Result<(), Self::Error> {
+        self.get_mut().send_item(item)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.get_mut().close();
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Inspects and manipulates a channel from the test without acting as a sender or receiver
+pub struct ChannelProbe<T> {
+    data: Arc<Mutex<ChannelData<T>>>,
+}
+
+impl<T> ChannelProbe<T> {
+    fn new(data: Arc<Mutex<ChannelData<T>>>) -> Self {
+        Self { data }
+    }
+
+    /// Copies of the queued messages, oldest first
+    pub fn queued(&self) -> Vec<T>
+    where
+        T: Clone,
+    {
+        self.data.lock().unwrap().queue.iter().cloned().collect()
+    }
+
+    /// Queues a message, ignoring the capacity. Fails if the receiver is closed.
+    pub fn inject(&self, value: T) -> Result<(), SendError<T>> {
+        let mut data = self.data.lock().unwrap();
+
+        if data.is_active {
+            data.enqueue(value);
+            check_rejected(&self.data, data);
+            Ok(())
+        } else {
+            Err(SendError(value))
+        }
+    }
+
+    /// Replace the fault policy of the channel. Messages held by the previous policy
+    /// are delivered right away.
+    pub fn set_faults(&self, policy: FaultPolicy<T>)
+    where
+        T: Send + 'static,
+    {
+        let faults = Faults::new(policy, release_held(&self.data));
+        let mut data = self.data.lock().unwrap();
+        data.set_faults(Some(faults));
+        check_rejected(&self.data, data);
+    }
+
+    /// Remove the fault policy, delivering any message it holds
+    pub fn clear_faults(&self) {
+        let mut data = self.data.lock().unwrap();
+        data.set_faults(None);
+        check_rejected(&self.data, data);
+    }
+
+    /// Removes and returns every queued message
+    pub fn drain(&self) -> Vec<T> {
+        let mut data = self.data.lock().unwrap();
+        let drained: Vec<T> = data.queue.drain(..).collect();
+        if !drained.is_empty() {
+            data.wake_senders();
+        }
+        drained
+    }
+
+    pub fn num_senders(&self) -> usize {
+        self.data.lock().unwrap().num_senders
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.lock().unwrap().queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Largest number of messages queued at once
+    pub fn high_water_mark(&self) -> usize {
+        self.data.lock().unwrap().high_water_mark
+    }
+
+    pub fn is_closed(&self) -> bool {
+        !self.data.lock().unwrap().is_active
+    }
+}
+
+impl<T> Clone for ChannelProbe<T> {
+    fn clone(&self) -> Self {
+        Self::new(self.data.clone())
+    }
+}
+
+impl<T> fmt::Debug for ChannelProbe<T> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let data = self.data.lock().unwrap();
+        fmt.debug_struct("ChannelProbe")
+            .field("len", &data.queue.len())
+            .field("num_senders", &data.num_senders)
+            .field("high_water_mark", &data.high_water_mark)
+            .finish()
+    }
+}
+
+pub fn channel<T>(buffer: usize) -> (Sender<T>, Receiver<T>) {
+    let data = Arc::new(Mutex::new(ChannelData::new(Some(buffer))));
+
+    (Sender::new(data.clone()), Receiver::new(data))
+}
+
+pub fn unbounded_channel<T>() -> (UnboundedSender<T>, UnboundedReceiver<T>) {
+    let data = Arc::new(Mutex::new(ChannelData::new(None)));
+
+    (
+        UnboundedSender::new(data.clone()),
+        UnboundedReceiver::new(data),
+    )
+}
+
+/// Bounded channel misbehaving according to `policy`
+pub fn channel_with_faults<T>(buffer: usize, policy: FaultPolicy<T>) -> (Sender<T>, Receiver<T>)
+where
+    T: Send + 'static,
+{
+    let (tx, rx) = channel(buffer);
+    rx.probe().set_faults(policy);
+    (tx, rx)
+}
+
+/// Unbounded channel misbehaving according to `policy`
+pub fn unbounded_channel_with_faults<T>(
+    policy: FaultPolicy<T>,
+) -> (UnboundedSender<T>, UnboundedReceiver<T>)
+where
+    T: Send + 'static,
+{
+    let (tx, rx) = unbounded_channel();
+    rx.probe().set_faults(policy);
+    (tx, rx)
+}
+
+// called by the mock clock when a held message is due
+fn release_held<T>(data: &Arc<Mutex<ChannelData<T>>>) -> fault::Release
+where
+    T: Send + 'static,
+{
+    let data = Arc::downgrade(data);
+    Arc::new(move |id| {
+        if let Some(data) = data.upgrade() {
+            let mut guard = data.lock().unwrap();
+            guard.release_held(id);
+            keep_rejected(&data, guard);
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod bounded {
+        use super::*;
+        use crate::mock::test::*;
+        use std::future::poll_fn;
+
+        #[test]
+        fn dropping_tx() {
+            let (tx, mut rx) = channel(16);
+            assert_pending!(spawn(async { rx.recv().await }).poll());
+            assert_ready!(spawn(async move {
+                tx.send(()).await.unwrap();
+                drop(tx);
+            })
+            .poll());
+            assert_ready_eq!(spawn(async { rx.recv().await }).poll(), Some(()));
+            assert_ready_eq!(spawn(async { rx.recv().await }).poll(), None);
+        }
+
+        #[test]
+        fn dropping_tx_try_recv() {
+            let (tx, mut rx) = channel(16);
+
+            assert_eq!(spawn(poll_fn(|cx| rx.poll_recv(cx))).poll(), Poll::Pending);
+            assert_ready!(spawn(async move {
+                tx.send(()).await.unwrap();
+                drop(tx);
+            })
+            .poll());
+            assert_eq!(
+                spawn(poll_fn(|cx| rx.poll_recv(cx))).poll(),
+                Poll::Ready(Some(()))
+            );
+            assert_eq!(
+                spawn(poll_fn(|cx| rx.poll_recv(cx))).poll(),
+                Poll::Ready(None)
+            );
+        }
+
+        #[test]
+        fn dropping_rx() {
+            let (tx1, rx) = channel(16);
+            let tx2 = tx1.clone();
+
+            assert_ready_ok!(spawn(async { tx1.send(()).await }).poll());
+            assert_ready_ok!(spawn(async { tx2.send(()).await }).poll());
+
+            drop(rx);
+
+            assert_ready_err!(spawn(async { tx1.send(()).await }).poll());
+            assert_ready_err!(spawn(async { tx2.send(()).await }).poll());
+        }
+
+        #[test]
+        fn dropping_rx_try_send() {
+            let (tx1, rx) = channel(16);
+            let tx2 = tx1.clone();
+
+            assert!(tx1.try_send(()).is_ok());
+            assert!(tx2.try_send(()).is_ok());
+
+            drop(rx);
+
+            assert!(matches!(tx1.try_send(()), Err(TrySendError::Closed(()))));
+            assert!(matches!(tx2.try_send(()), Err(TrySendError::Closed(()))));
+        }
+
+        #[test]
+        fn queue_full() {
+            let (tx, mut rx) = channel(16);
+
+            assert_ready!(spawn(async {
+                for _ in 0..16usize {
+                    tx.send(()).await.unwrap();
+                }
+            })
+            .poll());
+            assert_pending!(spawn(async {
+                tx.send(()).await.unwrap();
+            })
+            .poll());
+            assert_ready!(spawn(async { rx.recv().await }).poll());
+            assert_ready!(spawn(async {
+                tx.send(()).await.unwrap();
+            })
+            .poll());
+        }
+
+        #[test]
+        fn queue_full_try_send() {
+            let (tx, mut rx) = channel(16);
+
+            for _ in 0..16 {
+                assert!(tx.try_send(()).is_ok());
+            }
+            assert!(matches!(tx.try_send(()), Err(TrySendError::Full(()))));
+            assert_eq!(
+                spawn(poll_fn(|cx| rx.poll_recv(cx))).poll(),
+                Poll::Ready(Some(()))
+            );
+            assert!(tx.try_send(()).is_ok());
+        }
+
+        #[test]
+        fn send_wakes_receiver() {
+            let (tx, mut rx) = channel(16);
+            let mut rx_task = spawn(async move { rx.recv().await });
+
+            assert_pending!(rx_task.poll());
+            assert_ready_ok!(spawn(async { tx.send(42).await }).poll());
+            assert!(rx_task.is_woken());
+            assert_ready_eq!(rx_task.poll(), Some(42));
+        }
+
+        #[test]
+        fn recv_wakes_blocked_sender() {
+            let (tx, mut rx) = channel(1);
+
+            assert!(tx.try_send(1).is_ok());
+            let mut tx_task = spawn(async move { tx.send(2).await });
+            assert_pending!(tx_task.poll());
+            assert_eq!(rx.try_recv(), Ok(1));
+            assert!(tx_task.is_woken());
+            assert_ready_ok!(tx_task.poll());
+        }
+
+        #[test]
+        fn repolling_blocked_tasks_keeps_one_waker() {
+            let (tx, _rx) = channel(1);
+
+            assert!(tx.try_send(1).is_ok());
+            let mut tx_task = spawn(async { tx.send(2).await });
+            let mut closed_task = spawn(async { tx.closed().await });
+            for _ in 0..1000 {
+                assert_pending!(tx_task.poll());
+                assert_pending!(closed_task.poll());
+            }
+            assert_eq!(tx_task.waker_ref_count(), 2);
+            assert_eq!(closed_task.waker_ref_count(), 2);
+        }
+
+        #[test]
+        fn dropping_last_tx_wakes_receiver() {
+            let (tx1, mut rx) = channel::<()>(16);
+            let tx2 = tx1.clone();
+            let mut rx_task = spawn(async move { rx.recv().await });
+
+            assert_pending!(rx_task.poll());
+            drop(tx1);
+            assert!(!rx_task.is_woken());
+            drop(tx2);
+            assert!(rx_task.is_woken());
+            assert_ready_eq!(rx_task.poll(), None);
+        }
+
+        #[test]
+        fn close_wakes_blocked_sender() {
+            let (tx, mut rx) = channel(1);
+
+            assert!(tx.try_send(1).is_ok());
+            let mut tx_task = spawn(async move { tx.send(2).await });
+            assert_pending!(tx_task.poll());
+            rx.close();
+            assert!(tx_task.is_woken());
+            assert_ready_err!(tx_task.poll());
+        }
+
+        #[tokio::test]
+        async fn works_on_runtime() {
+            let (tx, mut rx) = channel(1);
+
+            let consumer = tokio::spawn(async move {
+                let mut sum = 0;
+                while let Some(value) = rx.recv().await {
+                    sum += value;
+                }
+                sum
+            });
+            for value in 0..100u32 {
+                tx.send(value).await.unwrap();
+            }
+            drop(tx);
+
+            assert_eq!(consumer.await.unwrap(), 4950);
+        }
+
+        #[test]
+        fn permit_holds_capacity() {
+            let (tx, mut rx) = channel(2);
+
+            let permit = assert_ok!(tx.try_reserve());
+            assert!(tx.try_send(1).is_ok());
+            assert!(matches!(tx.try_send(2), Err(TrySendError::Full(2))));
+            permit.send(0);
+            assert_eq!(rx.try_recv(), Ok(1));
+            assert_eq!(rx.try_recv(), Ok(0));
+        }
+
+        #[test]
+        fn dropping_permit_releases_capacity() {
+            let (tx, _rx) = channel::<()>(1);
+
+            let permit = assert_ok!(tx.try_reserve());
+            let mut reserve_task = spawn(async { tx.reserve().await.map(drop) });
+            assert_pending!(reserve_task.poll());
+            drop(permit);
+            assert!(reserve_task.is_woken());
+            assert_ready_ok!(reserve_task.poll());
+        }
+
+        #[test]
+        fn reserve_waits_for_capacity() {
+            let (tx, mut rx) = channel(1);
+
+            assert!(tx.try_send(1).is_ok());
+            let mut reserve_task = spawn(async {
+                let permit = tx.reserve().await.unwrap();
+                permit.send(2);
+            });
+            assert_pending!(reserve_task.poll());
+            assert_eq!(rx.try_recv(), Ok(1));
+            assert!(reserve_task.is_woken());
+            assert_ready!(reserve_task.poll());
+            assert_eq!(rx.try_recv(), Ok(2));
+        }
+
+        #[test]
+        fn reserve_on_closed_channel() {
+            let (tx, rx) = channel::<()>(1);
+
+            drop(rx);
+            assert!(matches!(tx.try_reserve(), Err(TrySendError::Closed(()))));
+            assert_ready_err!(spawn(async { tx.reserve().await.map(drop) }).poll());
+        }
+
+        #[test]
+        fn owned_permit() {
+            let (tx, mut rx) = channel(1);
+
+            let permit = assert_ready_ok!(spawn(tx.reserve_owned()).poll());
+            let tx = permit.send(1);
+            let tx = match tx.try_reserve_owned() {
+                Err(TrySendError::Full(tx)) => tx,
+                _ => panic!("channel should be full"),
+            };
+            assert_eq!(rx.try_recv(), Ok(1));
+            let permit = assert_ok!(tx.try_reserve_owned());
+            let tx = permit.release();
+            assert!(tx.try_send(2).is_ok());
+            assert_eq!(rx.try_recv(), Ok(2));
+        }
+
+        #[test]
+        fn closed_completes_when_rx_dropped() {
+            let (tx, rx) = channel::<()>(1);
+            let mut closed_task = spawn(async { tx.closed().await });
+
+            assert_pending!(closed_task.poll());
+            assert!(!tx.is_closed());
+            drop(rx);
+            assert!(closed_task.is_woken());
+            assert_ready!(closed_task.poll());
+            assert!(tx.is_closed());
+        }
+
+        #[test]
+        fn capacity_accounts_for_queue_and_permits() {
+            let (tx, mut rx) = channel(4);
+
+            assert_eq!(tx.max_capacity(), 4);
+            assert!(tx.try_send(1).is_ok());
+            let permit = assert_ok!(tx.try_reserve());
+            assert_eq!(tx.capacity(), 2);
+            drop(permit);
+            assert_eq!(rx.try_recv(), Ok(1));
+            assert_eq!(tx.capacity(), 4);
+        }
+
+        #[test]
+        fn same_channel() {
+            let (tx1, _rx1) = channel::<()>(1);
+            let (tx2, _rx2) = channel::<()>(1);
+
+            assert!(tx1.same_channel(&tx1.clone()));
+            assert!(!tx1.same_channel(&tx2));
+        }
+
+        #[test]
+        fn weak_sender_does_not_keep_channel_open() {
+            let (tx, mut rx) = channel::<u32>(1);
+            let weak = tx.downgrade();
+
+            let upgraded = weak.upgrade().unwrap();
+            assert!(upgraded.try_send(1).is_ok());
+            drop(upgraded);
+            drop(tx);
+            assert!(weak.upgrade().is_none());
+            assert_eq!(rx.try_recv(), Ok(1));
+            assert_ready_eq!(spawn(rx.recv()).poll(), None);
+        }
+
+        #[test]
+        fn send_timeout_elapses_on_mock_clock() {
+            let (tx, mut rx) = channel(1);
+            tx.try_send(1).unwrap();
+
+            let mut send_task = spawn(tx.send_timeout(2, Duration::from_secs(1)));
+
+            assert_pending!(send_task.poll());
+            crate::mock::time::advance(Duration::from_millis(999));
+            assert_pending!(send_task.poll());
+            crate::mock::time::advance(Duration::from_millis(1));
+            assert!(send_task.is_woken());
+            assert_ready_eq!(send_task.poll(), Err(SendTimeoutError::Timeout(2)));
+            assert_eq!(rx.try_recv(), Ok(1));
+        }
+
+        #[test]
+        fn send_timeout_completes_when_capacity_frees() {
+            let (tx, mut rx) = channel(1);
+            tx.try_send(1).unwrap();
+
+            let mut send_task = spawn(tx.send_timeout(2, Duration::from_secs(1)));
+
+            assert_pending!(send_task.poll());
+            assert_eq!(rx.try_recv(), Ok(1));
+            assert!(send_task.is_woken());
+            assert_ready_ok!(send_task.poll());
+            drop(rx);
+            assert_ready_eq!(
+                spawn(tx.send_timeout(3, Duration::from_secs(1))).poll(),
+                Err(SendTimeoutError::Closed(3))
+            );
+        }
+
+        #[test]
+        fn probe_inspects_queue_and_senders() {
+            let (tx, mut rx) = channel(2);
+            let probe = rx.probe();
+
+            tx.try_send(1).unwrap();
+            tx.try_send(2).unwrap();
+            let tx2 = tx.clone();
+            assert_eq!(probe.queued(), vec![1, 2]);
+            assert_eq!(probe.len(), 2);
+            assert_eq!(probe.num_senders(), 2);
+            assert_eq!(rx.try_recv(), Ok(1));
+            drop(tx2);
+            assert_eq!(probe.num_senders(), 1);
+            assert_eq!(probe.high_water_mark(), 2);
+        }
+
+        #[test]
+        fn probe_drain_frees_capacity() {
+            let (tx, _rx) = channel(1);
+            let probe = tx.probe();
+            tx.try_send(1).unwrap();
+
+            let mut send_task = spawn(tx.send(2));
+            assert_pending!(send_task.poll());
+            assert_eq!(probe.drain(), vec![1]);
+            assert!(send_task.is_woken());
+            assert_ready_ok!(send_task.poll());
+            assert_eq!(probe.queued(), vec![2]);
+        }
+
+        #[test]
+        fn probe_inject_wakes_receiver() {
+            let (tx, mut rx) = channel(1);
+            let probe = tx.probe();
+
+            let mut recv_task = spawn(rx.recv());
+            assert_pending!(recv_task.poll());
+            assert!(probe.inject(1).is_ok());
+            assert!(recv_task.is_woken());
+            assert_ready_eq!(recv_task.poll(), Some(1));
+            drop(recv_task);
+
+            tx.try_send(2).unwrap();
+            assert!(probe.inject(3).is_ok());
+            assert_eq!(probe.queued(), vec![2, 3]);
+            drop(rx);
+            assert!(probe.is_closed());
+            assert!(probe.inject(4).is_err());
+        }
+
+        #[test]
+        fn capacity_after_inject_into_full_channel() {
+            let (tx, mut rx) = channel(1);
+            let probe = tx.probe();
+
+            tx.try_send(1).unwrap();
+            assert!(probe.inject(2).is_ok());
+            assert_eq!(tx.capacity(), 0);
+            assert!(matches!(tx.try_send(3), Err(TrySendError::Full(3))));
+            assert_eq!(rx.try_recv(), Ok(1));
+            assert_eq!(tx.capacity(), 0);
+            assert_eq!(rx.try_recv(), Ok(2));
+            assert_eq!(tx.capacity(), 1);
+        }
+
+        #[test]
+        fn blocking_send_waits_for_async_receiver() {
+            let (tx, mut rx) = channel(1);
+            tx.try_send(1).unwrap();
+
+            let thread = std::thread::spawn(move || tx.blocking_send(2));
+
+            assert_ready_eq!(spawn(rx.recv()).poll(), Some(1));
+            let mut recv_task = spawn(rx.recv());
+            while recv_task.poll().is_pending() {
+                std::thread::yield_now();
+            }
+            assert!(thread.join().unwrap().is_ok());
+        }
+
+        #[test]
+        fn blocking_send_fails_when_receiver_drops() {
+            let (tx, rx) = channel(1);
+            tx.try_send(1).unwrap();
+
+            let thread = std::thread::spawn(move || tx.blocking_send(2));
+
+            drop(rx);
+            assert_eq!(thread.join().unwrap(), Err(SendError(2)));
+        }
+
+        #[test]
+        fn blocking_recv_wakes_on_async_send() {
+            let (tx, mut rx) = channel(1);
+
+            let thread = std::thread::spawn(move || (rx.blocking_recv(), rx.blocking_recv()));
+
+            assert_ready_ok!(spawn(tx.send(42)).poll());
+            drop(tx);
+            assert_eq!(thread.join().unwrap(), (Some(42), None));
+        }
+
+        #[test]
+        fn try_recv_after_senders_dropped_matches_tokio() {
+            let results = assert_parity!(mpsc, {
+                let (tx, mut rx) = mpsc::channel::<u32>(4);
+                let tx2 = tx.clone();
+                let mut results = vec![rx.try_recv()];
+                tx.try_send(1).unwrap();
+                tx2.try_send(2).unwrap();
+                drop(tx);
+                results.push(rx.try_recv());
+                drop(tx2);
+                results.push(rx.try_recv());
+                results.push(rx.try_recv());
+                results
+            });
+
+            assert_eq!(results.last(), Some(&Err(TryRecvError::Disconnected)));
+        }
+
+        #[test]
+        fn recv_many_matches_tokio() {
+            let results = assert_parity!(mpsc, {
+                let (tx, mut rx) = mpsc::channel::<u32>(8);
+                for value in 1..=5 {
+                    tx.try_send(value).unwrap();
+                }
+                let mut buffer = Vec::new();
+                let mut counts = vec![assert_ready!(spawn(rx.recv_many(&mut buffer, 0)).poll())];
+                counts.push(assert_ready!(spawn(rx.recv_many(&mut buffer, 3)).poll()));
+                counts.push(assert_ready!(spawn(rx.recv_many(&mut buffer, 10)).poll()));
+                let pending = spawn(rx.recv_many(&mut buffer, 10)).poll().is_pending();
+                drop(tx);
+                counts.push(assert_ready!(spawn(rx.recv_many(&mut buffer, 10)).poll()));
+                (counts, buffer, pending)
+            });
+
+            assert_eq!(results.0, vec![0, 3, 2, 0]);
+        }
+    }
+
+    mod unbounded {
+        use super::*;
+        use crate::mock::test::*;
+        use std::future::poll_fn;
+
+        #[test]
+        fn dropping_tx() {
+            let (tx, mut rx) = unbounded_channel();
+
+            assert_pending!(spawn(async { rx.recv().await }).poll());
+            tx.send(()).unwrap();
+            drop(tx);
+            assert_ready_eq!(spawn(async { rx.recv().await }).poll(), Some(()));
+            assert_ready_eq!(spawn(async { rx.recv().await }).poll(), None);
+        }
+
+        #[test]
+        fn dropping_tx_try_recv() {
+            let (tx, mut rx) = unbounded_channel();
+
+            assert_eq!(spawn(poll_fn(|cx| rx.poll_recv(cx))).poll(), Poll::Pending);
+            tx.send(()).unwrap();
+            drop(tx);
+            assert_eq!(
+                spawn(poll_fn(|cx| rx.poll_recv(cx))).poll(),
+                Poll::Ready(Some(()))
+            );
+            assert_eq!(
+                spawn(poll_fn(|cx| rx.poll_recv(cx))).poll(),
+                Poll::Ready(None)
+            );
+        }
+
+        #[test]
+        fn dropping_rx() {
+            let (tx1, rx) = unbounded_channel();
+            let tx2 = tx1.clone();
+
+            assert!(tx1.send(()).is_ok());
+            assert!(tx2.send(()).is_ok());
+
+            drop(rx);
+
+            assert!(tx1.send(()).is_err());
+            assert!(tx2.send(()).is_err());
+        }
+
+        #[test]
+        fn send_wakes_receiver() {
+            let (tx, mut rx) = unbounded_channel();
+            let mut rx_task = spawn(async move { rx.recv().await });
+
+            assert_pending!(rx_task.poll());
+            tx.send(42).unwrap();
+            assert!(rx_task.is_woken());
+            assert_ready_eq!(rx_task.poll(), Some(42));
+        }
+
+        #[test]
+        fn closed_completes_when_rx_closed() {
+            let (tx, mut rx) = unbounded_channel::<()>();
+            let mut closed_task = spawn(async { tx.closed().await });
+
+            assert_pending!(closed_task.poll());
+            rx.close();
+            assert!(closed_task.is_woken());
+            assert_ready!(closed_task.poll());
+            assert!(tx.is_closed());
+        }
+
+        #[test]
+        fn weak_sender_does_not_keep_channel_open() {
+            let (tx, mut rx) = unbounded_channel::<u32>();
+            let weak = tx.downgrade();
+
+            assert!(weak.upgrade().unwrap().send(1).is_ok());
+            assert!(tx.same_channel(&weak.upgrade().unwrap()));
+            drop(tx);
+            assert!(weak.upgrade().is_none());
+            assert_eq!(rx.try_recv(), Ok(1));
+            assert_ready_eq!(spawn(rx.recv()).poll(), None);
+        }
+
+        #[test]
+        fn blocking_recv_returns_none_when_senders_drop() {
+            let (tx, mut rx) = unbounded_channel::<u32>();
+
+            let thread = std::thread::spawn(move || rx.blocking_recv());
+
+            drop(tx);
+            assert_eq!(thread.join().unwrap(), None);
+        }
+
+        #[test]
+        fn try_recv_after_close_matches_tokio() {
+            assert_parity!(mpsc, {
+                let (tx, mut rx) = mpsc::unbounded_channel::<u32>();
+                tx.send(1).unwrap();
+                rx.close();
+                let sent_after_close = tx.send(2).is_ok();
+                let results = vec![rx.try_recv(), rx.try_recv()];
+                let last = assert_ready!(spawn(rx.recv()).poll());
+                (sent_after_close, results, last)
+            });
+        }
+
+        #[test]
+        fn recv_many_wakes_on_send() {
+            let (tx, mut rx) = unbounded_channel::<u32>();
+            let mut buffer = Vec::new();
+            let mut task = spawn(rx.recv_many(&mut buffer, 4));
+
+            assert_pending!(task.poll());
+            tx.send(1).unwrap();
+            tx.send(2).unwrap();
+            assert!(task.is_woken());
+            assert_ready_eq!(task.poll(), 2);
+            drop(task);
+            assert_eq!(buffer, vec![1, 2]);
+        }
+    }
+
+    #[cfg(feature = "futures")]
+    mod futures {
+        use super::*;
+        use crate::mock::test::*;
+        use futures_core::Stream;
+        use futures_sink::Sink;
+        use std::future::poll_fn;
+
+        #[test]
+        fn wrappers_match_tokio() {
+            // the same pipeline code compiles against both facades
+            let results = assert_parity!(mpsc, {
+                let (tx, rx) = mpsc::channel::<u32>(1);
+                let (out_tx, mut out_rx) = mpsc::channel::<u32>(4);
+                let mut stream = mpsc::ReceiverStream::new(rx);
+                let mut sink = mpsc::PollSender::new(out_tx);
+
+                tx.try_send(1).unwrap();
+                drop(tx);
+                let mut forward = spawn(poll_fn(|cx| loop {
+                    std::task::ready!(Pin::new(&mut sink).poll_ready(cx)).unwrap();
+                    match std::task::ready!(Pin::new(&mut stream).poll_next(cx)) {
+                        Some(value) => Pin::new(&mut sink).start_send(value * 10).unwrap(),
+                        None => return Poll::Ready(()),
+                    }
+                }));
+                assert_ready!(forward.poll());
+                drop(forward);
+
+                let closed = sink.is_closed();
+                sink.close();
+                (
+                    out_rx.try_recv(),
+                    closed,
+                    sink.is_closed(),
+                    out_rx.try_recv(),
+                )
+            });
+
+            assert_eq!(results.0, Ok(10));
+        }
+
+        #[test]
+        fn receiver_is_a_stream() {
+            let (tx, mut rx) = unbounded_channel::<u32>();
+            let mut next = spawn(poll_fn(|cx| Pin::new(&mut rx).poll_next(cx)));
+
+            assert_pending!(next.poll());
+            tx.send(1).unwrap();
+            assert!(next.is_woken());
+            assert_ready_eq!(next.poll(), Some(1));
+            drop(tx);
+            assert_ready_eq!(next.poll(), None);
+        }
+
+        #[test]
+        fn sender_sink_waits_for_capacity() {
+            let (mut tx, mut rx) = channel::<u32>(1);
+
+            assert_ready_ok!(spawn(poll_fn(|cx| Pin::new(&mut tx).poll_ready(cx))).poll());
+            assert!(Pin::new(&mut tx).start_send(1).is_ok());
+            // a second item needs a new slot
+            assert!(Pin::new(&mut tx).start_send(2).is_err());
+
+            let mut ready = spawn(poll_fn(|cx| Pin::new(&mut tx).poll_ready(cx)));
+            assert_pending!(ready.poll());
+            assert_eq!(rx.try_recv(), Ok(1));
+            assert!(ready.is_woken());
+            assert_ready_ok!(ready.poll());
+            drop(ready);
+
+            // dropping the sender gives back the reserved slot
+            assert_eq!(tx.capacity(), 0);
+            let tx2 = tx.clone();
+            drop(tx);
+            assert_eq!(tx2.capacity(), 1);
+        }
+
+        #[test]
+        fn unbounded_sender_sink_fails_when_closed() {
+            let (mut tx, rx) = unbounded_channel::<u32>();
+
+            assert_ready_ok!(spawn(poll_fn(|cx| Pin::new(&mut tx).poll_ready(cx))).poll());
+            assert!(Pin::new(&mut tx).start_send(1).is_ok());
+            drop(rx);
+            assert_ready_err!(spawn(poll_fn(|cx| Pin::new(&mut tx).poll_ready(cx))).poll());
+        }
+    }
+}
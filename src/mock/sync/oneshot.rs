@@ -2,8 +2,8 @@ use error::{RecvError, TryRecvError};
 use std::fmt;
 use std::future::Future;
 use std::pin::Pin;
-use std::sync::{Arc, Mutex};
-use std::task::{Context, Poll};
+use std::sync::{Arc, Condvar, Mutex};
+use std::task::{Context, Poll, Waker};
 
 pub mod error {
     #[derive(Debug, Eq, PartialEq)]
@@ -24,6 +24,10 @@ struct ChannelData<T> {
     msg: Option<T>,
     is_recv_dropped: bool,
     is_send_dropped: bool,
+    recv_waker: Option<Waker>,
+    closed_waker: Option<Waker>,
+    // notified on send and sender drop for a thread in blocking_recv
+    blocked: Arc<Condvar>,
 }
 
 impl<T> ChannelData<T> {
@@ -32,18 +36,73 @@ impl<T> ChannelData<T> {
             msg: None,
             is_recv_dropped: false,
             is_send_dropped: false,
+            recv_waker: None,
+            closed_waker: None,
+            blocked: Arc::new(Condvar::new()),
         }
     }
 
+    // a value sent before the receiver closed can still be received, like tokio
     fn try_recv(&mut self) -> Result<T, TryRecvError> {
         if let Some(msg) = self.msg.take() {
             Ok(msg)
-        } else if self.is_send_dropped {
+        } else if self.is_send_dropped || self.is_recv_dropped {
             Err(TryRecvError::Closed)
         } else {
             Err(TryRecvError::Empty)
         }
     }
+
+    fn poll_recv(&mut self, cx: &mut Context<'_>) -> Poll<Result<T, RecvError>> {
+        match self.try_recv() {
+            Ok(value) => Poll::Ready(Ok(value)),
+            Err(TryRecvError::Closed) => Poll::Ready(Err(RecvError)),
+            Err(TryRecvError::Empty) => {
+                self.recv_waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+
+    fn poll_closed(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        if self.is_recv_dropped {
+            Poll::Ready(())
+        } else {
+            self.closed_waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+
+    fn send(&mut self, value: T) -> Result<(), T> {
+        if self.is_recv_dropped {
+            return Err(value);
+        }
+
+        self.msg.replace(value);
+        self.wake_receiver();
+        Ok(())
+    }
+
+    fn close(&mut self) {
+        self.is_recv_dropped = true;
+        self.recv_waker = None;
+        if let Some(waker) = self.closed_waker.take() {
+            waker.wake();
+        }
+    }
+
+    fn drop_sender(&mut self) {
+        self.is_send_dropped = true;
+        self.closed_waker = None;
+        self.wake_receiver();
+    }
+
+    fn wake_receiver(&mut self) {
+        if let Some(waker) = self.recv_waker.take() {
+            waker.wake();
+        }
+        self.blocked.notify_all();
+    }
 }
 
 struct IsClosedFuture<T> {
@@ -53,14 +112,8 @@ struct IsClosedFuture<T> {
 impl<T> Future for IsClosedFuture<T> {
     type Output = ();
 
-    fn poll(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Self::Output> {
-        let data = self.data.lock().unwrap();
-
-        if data.is_recv_dropped {
-            Poll::Ready(())
-        } else {
-            Poll::Pending
-        }
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.data.lock().unwrap().poll_closed(cx)
     }
 }
 
@@ -77,22 +130,28 @@ impl<T> Receiver<T> {
         self.data.lock().unwrap().try_recv()
     }
 
-    pub fn close(&mut self) {
-        let mut data = self.data.lock().unwrap();
+    /// Blocks the current thread until a value is sent or the sender is dropped
+    pub fn blocking_recv(self) -> Result<T, RecvError> {
+        let data = self.data.lock().unwrap();
+        let blocked = data.blocked.clone();
+        super::wait_for(data, &blocked, |data| match data.try_recv() {
+            Ok(value) => Some(Ok(value)),
+            Err(TryRecvError::Closed) => Some(Err(RecvError)),
+            Err(TryRecvError::Empty) => None,
+        })
+    }
 
-        data.is_recv_dropped = true;
+    /// Prevents the sender from sending. A value sent before the call can still be received.
+    pub fn close(&mut self) {
+        self.data.lock().unwrap().close();
     }
 }
 
 impl<T> Future for Receiver<T> {
     type Output = Result<T, RecvError>;
 
-    fn poll(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Self::Output> {
-        match self.try_recv() {
-            Ok(value) => Poll::Ready(Ok(value)),
-            Err(TryRecvError::Closed) => Poll::Ready(Err(RecvError)),
-            Err(TryRecvError::Empty) => Poll::Pending,
-        }
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.data.lock().unwrap().poll_recv(cx)
     }
 }
 
@@ -118,14 +177,7 @@ impl<T> Sender<T> {
     }
 
     pub fn send(self, value: T) -> Result<(), T> {
-        let mut data = self.data.lock().unwrap();
-
-        if !data.is_recv_dropped {
-            data.msg.replace(value);
-            Ok(())
-        } else {
-            Err(value)
-        }
+        self.data.lock().unwrap().send(value)
     }
 
     pub fn closed(&mut self) -> impl Future<Output = ()> {
@@ -134,6 +186,10 @@ impl<T> Sender<T> {
         }
     }
 
+    pub fn poll_closed(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        self.data.lock().unwrap().poll_closed(cx)
+    }
+
     pub fn is_closed(&self) -> bool {
         self.data.lock().unwrap().is_recv_dropped
     }
@@ -141,8 +197,7 @@ impl<T> Sender<T> {
 
 impl<T> Drop for Sender<T> {
     fn drop(&mut self) {
-        let mut data = self.data.lock().unwrap();
-        data.is_send_dropped = true;
+        self.data.lock().unwrap().drop_sender();
     }
 }
 
@@ -168,7 +223,7 @@ mod tests {
     fn send_recv() {
         let (tx, rx) = channel();
 
-        let mut rx_task = spawn(async move { rx.await });
+        let mut rx_task = spawn(rx);
 
         assert_pending!(rx_task.poll());
         assert!(tx.send(42).is_ok());
@@ -179,7 +234,7 @@ mod tests {
     fn dropping_tx() {
         let (tx, rx) = channel::<()>();
 
-        let mut rx_task = spawn(async { rx.await });
+        let mut rx_task = spawn(rx);
 
         assert_pending!(rx_task.poll());
         drop(tx);
@@ -215,4 +270,90 @@ mod tests {
         drop(rx);
         assert_ready!(closed_task.poll());
     }
+
+    #[test]
+    fn blocking_recv() {
+        let (tx, rx) = channel();
+
+        let thread = std::thread::spawn(move || rx.blocking_recv());
+
+        assert!(tx.send(42).is_ok());
+        assert_eq!(thread.join().unwrap(), Ok(42));
+    }
+
+    #[test]
+    fn blocking_recv_dropping_tx() {
+        let (tx, rx) = channel::<()>();
+
+        let thread = std::thread::spawn(move || rx.blocking_recv());
+
+        drop(tx);
+        assert_eq!(thread.join().unwrap(), Err(RecvError));
+    }
+
+    #[test]
+    fn send_wakes_receiver() {
+        let (tx, rx) = channel();
+
+        let mut rx_task = spawn(rx);
+
+        assert_pending!(rx_task.poll());
+        assert!(tx.send(42).is_ok());
+        assert!(rx_task.is_woken());
+        assert_ready_eq!(rx_task.poll(), Ok(42));
+    }
+
+    #[test]
+    fn dropping_tx_wakes_receiver() {
+        let (tx, rx) = channel::<()>();
+
+        let mut rx_task = spawn(rx);
+
+        assert_pending!(rx_task.poll());
+        drop(tx);
+        assert!(rx_task.is_woken());
+        assert_ready_err!(rx_task.poll());
+    }
+
+    #[test]
+    fn close_wakes_poll_closed() {
+        let (mut tx, mut rx) = channel::<()>();
+
+        let mut closed_task = spawn(std::future::poll_fn(|cx| tx.poll_closed(cx)));
+
+        assert_pending!(closed_task.poll());
+        rx.close();
+        assert!(closed_task.is_woken());
+        assert_ready!(closed_task.poll());
+    }
+
+    #[test]
+    fn close_matches_tokio() {
+        for send_first in [true, false] {
+            assert_parity!(oneshot, {
+                let (tx, mut rx) = oneshot::channel::<u32>();
+                let sent = send_first && tx.send(1).is_ok();
+                rx.close();
+                let first = format!("{:?}", rx.try_recv());
+                let second = format!("{:?}", rx.try_recv());
+                (sent, first, second)
+            });
+        }
+    }
+
+    #[test]
+    fn send_after_close_matches_tokio() {
+        let results = assert_parity!(oneshot, {
+            let (mut tx, mut rx) = oneshot::channel::<u32>();
+            let mut closed_task = spawn(std::future::poll_fn(|cx| tx.poll_closed(cx)));
+            let pending = closed_task.poll().is_pending();
+            rx.close();
+            let woken = closed_task.is_woken();
+            assert_ready!(closed_task.poll());
+            drop(closed_task);
+            (pending, woken, tx.is_closed(), tx.send(1))
+        });
+
+        assert_eq!(results.3, Err(1));
+    }
 }
@@ -0,0 +1,267 @@
+use crate::mock::sync::mpsc::Receiver;
+use crate::mock::test::scripted::{Script, ScriptedReceiver};
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::Mutex;
+
+struct Expectation<T> {
+    description: String,
+    matches: Box<dyn FnMut(&T) -> bool + Send>,
+}
+
+struct Expectations<T> {
+    queue: VecDeque<Expectation<T>>,
+}
+
+impl<T> Script for Expectations<T>
+where
+    T: fmt::Debug + Send + 'static,
+{
+    type Message = T;
+
+    fn check(script: &Mutex<Self>, value: T) -> Result<(), String> {
+        // the script stays unlocked while the predicate runs, so a panic in it leaves the
+        // script usable; the channel is unlocked too, so the predicate may inspect it
+        let expectation = script.lock().unwrap().queue.pop_front();
+
+        match expectation {
+            Some(mut expectation) => {
+                if (expectation.matches)(&value) {
+                    return Ok(());
+                }
+                let err = format!(
+                    r#"unexpected message:
+ expected: {},
+ received: {:?}"#,
+                    expectation.description, value
+                );
+                script.lock().unwrap().queue.push_front(expectation);
+                Err(err)
+            }
+            None => Err(format!("no more messages expected, received: {:?}", value)),
+        }
+    }
+
+    fn unmet(&self) -> Option<String> {
+        if self.queue.is_empty() {
+            return None;
+        }
+
+        let unmet: Vec<String> = self
+            .queue
+            .iter()
+            .map(|expectation| expectation.description.clone())
+            .collect();
+
+        Some(format!(
+            "channel script contained {} unmet expectations on drop: {}",
+            unmet.len(),
+            unmet.join(", ")
+        ))
+    }
+}
+
+/// Receiving end of a mock mpsc channel that checks each message against queued expectations
+/// as it is sent. Sending a message that doesn't match the next expectation panics in the sender.
+/// Messages already queued when it is created have nothing to match and panic right away.
+pub struct ExpectReceiver<T>
+where
+    T: fmt::Debug + Send + 'static,
+{
+    rx: ScriptedReceiver<Expectations<T>>,
+}
+
+impl<T> ExpectReceiver<T>
+where
+    T: fmt::Debug + Send + 'static,
+{
+    pub fn new(rx: Receiver<T>) -> Self {
+        let expectations = Expectations {
+            queue: VecDeque::new(),
+        };
+
+        Self {
+            rx: ScriptedReceiver::new(rx, expectations),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of expectations not met yet
+    pub fn len(&self) -> usize {
+        self.rx.script().queue.len()
+    }
+
+    /// Expect the next message to be equal to `value`
+    pub fn expect(&mut self, value: T)
+    where
+        T: PartialEq,
+    {
+        let description = format!("{:?}", value);
+        self.push(description, move |msg| *msg == value);
+    }
+
+    /// Expect the next message to satisfy `predicate`
+    pub fn expect_matching<F>(&mut self, description: &str, predicate: F)
+    where
+        F: FnMut(&T) -> bool + Send + 'static,
+    {
+        self.push(description.to_string(), predicate);
+    }
+
+    /// Panics if a message delivered without a sender, such as one a fault policy held until
+    /// the clock released it, did not match. Mismatches are otherwise reported by the sender.
+    pub fn verify(&self) {
+        self.rx.verify();
+    }
+
+    fn push<F>(&mut self, description: String, predicate: F)
+    where
+        F: FnMut(&T) -> bool + Send + 'static,
+    {
+        self.rx.script().queue.push_back(Expectation {
+            description,
+            matches: Box::new(predicate),
+        });
+    }
+}
+
+impl<T> fmt::Debug for ExpectReceiver<T>
+where
+    T: fmt::Debug + Send + 'static,
+{
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.debug_struct("ExpectReceiver").finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+    use super::*;
+    use crate::mock::sync::mpsc;
+    use crate::mock::time::Duration;
+
+    #[test]
+    fn checks_messages_in_order() {
+        let (tx, rx) = mpsc::channel(1);
+        let mut rx = ExpectReceiver::new(rx);
+
+        rx.expect(1);
+        rx.expect_matching("an even number", |x: &u32| x & 1 == 0);
+
+        assert_ready_ok!(spawn(tx.send(1)).poll());
+        assert_eq!(rx.len(), 1);
+        // matched messages don't take up capacity
+        assert!(tx.try_send(4).is_ok());
+        assert!(rx.is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "expected: an even number")]
+    fn panics_on_mismatch() {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let mut rx = ExpectReceiver::new(rx);
+
+        rx.expect_matching("an even number", |x: &u32| x & 1 == 0);
+        let _ = tx.send(3);
+    }
+
+    #[test]
+    #[should_panic(expected = "no more messages expected, received: 3")]
+    fn panics_on_unexpected_message() {
+        let (tx, rx) = mpsc::unbounded_channel::<u32>();
+        let _rx = ExpectReceiver::new(rx);
+
+        let _ = tx.send(3);
+    }
+
+    #[test]
+    #[should_panic(expected = "no more messages expected, received: 5")]
+    fn panics_on_message_queued_before_creation() {
+        let (tx, rx) = mpsc::channel::<u32>(1);
+
+        tx.try_send(5).unwrap();
+        let _rx = ExpectReceiver::new(rx);
+    }
+
+    #[test]
+    #[should_panic(expected = "too big: 12")]
+    fn panicking_predicate_fails_the_sender() {
+        let (tx, rx) = mpsc::channel(1);
+        let mut rx = ExpectReceiver::new(rx);
+
+        rx.expect_matching("a small number", |x: &u32| {
+            assert!(*x < 10, "too big: {}", x);
+            true
+        });
+        let _ = tx.try_send(12);
+    }
+
+    #[test]
+    fn predicate_may_inspect_the_channel() {
+        let (tx, rx) = mpsc::channel(1);
+        let probe = rx.probe();
+        let mut rx = ExpectReceiver::new(rx);
+
+        rx.expect_matching("an empty channel", move |_: &u32| probe.is_empty());
+        assert!(tx.try_send(1).is_ok());
+        assert!(rx.is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "expected: 1,\n received: 2")]
+    fn reports_mismatch_flushed_when_sender_drops() {
+        let policy = mpsc::FaultPolicy::new(0).swap_adjacent(1.0);
+        let (tx, rx) = mpsc::unbounded_channel_with_faults(policy);
+        let mut rx = ExpectReceiver::new(rx);
+
+        rx.expect(1);
+        tx.send(2).unwrap();
+        drop(tx);
+    }
+
+    #[test]
+    #[should_panic(expected = "expected: 1,\n received: 2")]
+    fn reports_mismatch_released_by_the_clock_on_drop() {
+        let until = time::Instant::now() + Duration::from_secs(1);
+        let policy = mpsc::FaultPolicy::new(0).hold_nth(1, until);
+        let (tx, rx) = mpsc::unbounded_channel_with_faults(policy);
+        let mut rx = ExpectReceiver::new(rx);
+
+        rx.expect(1);
+        tx.send(2).unwrap();
+        time::advance(Duration::from_secs(1));
+    }
+
+    #[test]
+    fn reports_mismatch_released_by_the_clock_on_verify() {
+        let until = time::Instant::now() + Duration::from_secs(1);
+        let policy = mpsc::FaultPolicy::new(0).hold_nth(1, until);
+        let (tx, rx) = mpsc::unbounded_channel_with_faults(policy);
+        let mut rx = ExpectReceiver::new(rx);
+
+        rx.expect(1);
+        tx.send(2).unwrap();
+        time::advance(Duration::from_secs(1));
+        // the mismatch is not blamed on a later, correct message
+        tx.send(1).unwrap();
+        drop(tx);
+
+        let verified = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| rx.verify()));
+        assert!(verified.is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "channel script contained 2 unmet expectations on drop: 1, 2")]
+    fn panics_on_drop_with_unmet_expectations() {
+        let (_tx, rx) = mpsc::channel::<u32>(1);
+        let mut rx = ExpectReceiver::new(rx);
+
+        rx.expect(1);
+        rx.expect(2);
+    }
+}
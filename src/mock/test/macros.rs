@@ -191,7 +191,7 @@ macro_rules! assert_ok {
 #[macro_export]
 macro_rules! assert_err {
     ($e:expr) => {
-        assert_err!($e,);
+        assert_err!($e,)
     };
     ($e:expr,) => {{
         use core::result::Result::*;
@@ -208,3 +208,24 @@ macro_rules! assert_err {
         }
     }};
 }
+
+/// Runs `$body` with `$module` naming the mock `sync::$module`, then again with it naming
+/// tokio's, and asserts both runs give the same result, which is returned.
+#[cfg(test)]
+macro_rules! assert_parity {
+    ($module:ident, $body:block) => {{
+        let mock = {
+            use crate::mock::sync::$module;
+            $body
+        };
+        let real = {
+            use crate::real::sync::$module;
+            $body
+        };
+        assert_eq!(mock, real, "the mock and tokio disagree");
+        mock
+    }};
+}
+
+#[cfg(test)]
+pub(crate) use assert_parity;
@@ -1,8 +1,15 @@
+pub mod channel;
 pub mod io;
 mod macros;
+pub mod responder;
+mod scripted;
 
-use std::ptr::null;
-use std::task::{Context, RawWaker, Waker};
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Weak};
+use std::task::{Context, Wake, Waker};
+
+use crate::mock::time;
 
 pub use crate::assert_err;
 pub use crate::assert_ok;
@@ -11,45 +18,146 @@ pub use crate::assert_ready;
 pub use crate::assert_ready_eq;
 pub use crate::assert_ready_err;
 pub use crate::assert_ready_ok;
+#[cfg(test)]
+pub(crate) use macros::assert_parity;
 
 pub struct Spawn<T>
 where
     T: std::future::Future,
 {
     future: std::pin::Pin<Box<T>>,
+    waker: Arc<WakeCounter>,
+    clock: Option<time::Clock>,
 }
 
 impl<T> Spawn<T>
 where
     T: std::future::Future,
 {
+    /// Poll the future once. If auto-advance is enabled and every task spawned on this
+    /// thread is left idle, the time is moved to the next pending deadline and the task
+    /// is polled again if that woke it up. The time jumps at most once per call, so a
+    /// task that always has a timer pending still returns.
     pub fn poll(&mut self) -> std::task::Poll<T::Output> {
-        let waker = unsafe { Waker::from_raw(RawWaker::new(null(), &details::NULL_WAKER_VTABLE)) };
+        let _guard = self.clock.as_ref().map(|clock| clock.enter());
+
+        let result = self.poll_once();
+        if result.is_ready() || self.is_woken() || !time::is_auto_advance() {
+            return result;
+        }
+
+        if is_other_task_woken(&self.waker) {
+            return result;
+        }
+
+        match time::next_deadline() {
+            Some(deadline) => time::advance_to(deadline),
+            None => return result,
+        }
+
+        if self.is_woken() {
+            self.poll_once()
+        } else {
+            result
+        }
+    }
+
+    fn poll_once(&mut self) -> std::task::Poll<T::Output> {
+        self.waker.reset();
+        let waker = Waker::from(self.waker.clone());
         let mut context = Context::from_waker(&waker);
         self.future.as_mut().poll(&mut context)
     }
+
+    /// Use `clock` as the current clock whenever the task is polled
+    pub fn with_clock(mut self, clock: time::Clock) -> Self {
+        self.clock = Some(clock);
+        self
+    }
+
+    /// Returns `true` if the task was woken since the last call to `poll`
+    pub fn is_woken(&self) -> bool {
+        self.waker.wake_count() > 0
+    }
+
+    /// Number of times the task was woken since the last call to `poll`
+    pub fn wake_count(&self) -> usize {
+        self.waker.wake_count()
+    }
+
+    /// Number of outstanding references to the task's waker, including the one held by `Spawn`
+    pub fn waker_ref_count(&self) -> usize {
+        Arc::strong_count(&self.waker)
+    }
+}
+
+impl<T> Drop for Spawn<T>
+where
+    T: std::future::Future,
+{
+    fn drop(&mut self) {
+        // timers may keep the waker alive, but a dropped task is never runnable again
+        let _ = TASKS.try_with(|tasks| {
+            tasks
+                .borrow_mut()
+                .retain(|task| !std::ptr::eq(task.as_ptr(), Arc::as_ptr(&self.waker)))
+        });
+    }
 }
 
 pub fn spawn<T>(f: T) -> Spawn<T>
 where
     T: std::future::Future,
 {
+    let waker = Arc::new(WakeCounter::new());
+    TASKS.with(|tasks| tasks.borrow_mut().push(Arc::downgrade(&waker)));
     Spawn {
         future: Box::pin(f),
+        waker,
+        clock: None,
     }
 }
 
-pub(crate) mod details {
-    use std::ptr::null;
-    use std::task::{RawWaker, RawWakerVTable};
+// wakers of the tasks spawned on this thread and not dropped yet, checked before auto-advancing
+thread_local!(static TASKS: RefCell<Vec<Weak<WakeCounter>>> = const { RefCell::new(Vec::new()) });
+
+// a woken task is still runnable, so the time must not move under it
+fn is_other_task_woken(waker: &Arc<WakeCounter>) -> bool {
+    TASKS.with(|tasks| {
+        tasks
+            .borrow()
+            .iter()
+            .filter_map(Weak::upgrade)
+            .any(|task| !Arc::ptr_eq(&task, waker) && task.wake_count() > 0)
+    })
+}
+
+struct WakeCounter {
+    count: AtomicUsize,
+}
+
+impl WakeCounter {
+    fn new() -> Self {
+        Self {
+            count: AtomicUsize::new(0),
+        }
+    }
+
+    fn reset(&self) {
+        self.count.store(0, Ordering::SeqCst);
+    }
+
+    fn wake_count(&self) -> usize {
+        self.count.load(Ordering::SeqCst)
+    }
+}
 
-    fn clone(_: *const ()) -> RawWaker {
-        RawWaker::new(null(), &NULL_WAKER_VTABLE)
+impl Wake for WakeCounter {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
     }
-    fn wake(_: *const ()) {}
-    fn wake_by_ref(_: *const ()) {}
-    fn drop(_: *const ()) {}
 
-    pub(crate) const NULL_WAKER_VTABLE: RawWakerVTable =
-        RawWakerVTable::new(clone, wake, wake_by_ref, drop);
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.count.fetch_add(1, Ordering::SeqCst);
+    }
 }
@@ -0,0 +1,304 @@
+use crate::mock::sync::mpsc::Receiver;
+use crate::mock::sync::oneshot;
+use crate::mock::test::scripted::{Script, ScriptedReceiver};
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Arc, Mutex, PoisonError};
+
+type Respond<Req, Resp> = Box<dyn FnMut(&Req) -> Option<Resp> + Send>;
+
+struct Expected<Req, Resp> {
+    request: Req,
+    response: Resp,
+    description: String,
+}
+
+struct Answers<Req, Resp> {
+    table: VecDeque<Expected<Req, Resp>>,
+    // locked on its own, so the closure stays in place if it panics
+    respond: Option<Arc<Mutex<Respond<Req, Resp>>>>,
+    answered: usize,
+}
+
+// the comparison and the closure run unlocked, so a panic in them leaves the answers usable
+fn answer<Req, Resp>(answers: &Mutex<Answers<Req, Resp>>, request: &Req) -> Result<Resp, String>
+where
+    Req: fmt::Debug + PartialEq,
+{
+    let expected = answers.lock().unwrap().table.pop_front();
+    if let Some(expected) = expected {
+        if expected.request != *request {
+            let err = format!(
+                r#"unexpected request:
+ expected: {},
+ received: {:?}"#,
+                expected.description, request
+            );
+            answers.lock().unwrap().table.push_front(expected);
+            return Err(err);
+        }
+        return Ok(expected.response);
+    }
+
+    let respond = match answers.lock().unwrap().respond.clone() {
+        Some(respond) => respond,
+        None => {
+            return Err(format!(
+                "no more requests expected, received: {:?}",
+                request
+            ))
+        }
+    };
+    // a previous panic in the closure was already reported to its sender
+    let mut respond = respond.lock().unwrap_or_else(PoisonError::into_inner);
+    respond(request).ok_or_else(|| format!("request not answered: {:?}", request))
+}
+
+impl<Req, Resp> Script for Answers<Req, Resp>
+where
+    Req: fmt::Debug + PartialEq + Send + 'static,
+    Resp: Send + 'static,
+{
+    type Message = (Req, oneshot::Sender<Resp>);
+
+    fn check(answers: &Mutex<Self>, (request, reply): Self::Message) -> Result<(), String> {
+        let response = answer(answers, &request)?;
+        answers.lock().unwrap().answered += 1;
+        // the requester may have given up waiting
+        let _ = reply.send(response);
+        Ok(())
+    }
+
+    fn unmet(&self) -> Option<String> {
+        if self.table.is_empty() {
+            return None;
+        }
+
+        let unmet: Vec<String> = self
+            .table
+            .iter()
+            .map(|expected| expected.description.clone())
+            .collect();
+
+        Some(format!(
+            "responder contained {} expected requests on drop: {}",
+            unmet.len(),
+            unmet.join(", ")
+        ))
+    }
+}
+
+/// Answers the requests of the command pattern, `(request, oneshot::Sender<response>)` sent
+/// over a mock mpsc channel, as soon as they are sent.
+///
+/// Requests are first matched in order against the expected requests of the table. Once the
+/// table is exhausted, they are handed to the closure given to [`Responder::with_fn`]. A request
+/// that doesn't match panics in the sender, and dropping the responder while expected requests
+/// remain panics with the list of them. Requests already queued on the channel are handled as
+/// soon as the responder is created.
+pub struct Responder<Req, Resp>
+where
+    Req: fmt::Debug + PartialEq + Send + 'static,
+    Resp: Send + 'static,
+{
+    rx: ScriptedReceiver<Answers<Req, Resp>>,
+}
+
+impl<Req, Resp> Responder<Req, Resp>
+where
+    Req: fmt::Debug + PartialEq + Send + 'static,
+    Resp: Send + 'static,
+{
+    pub fn new(rx: Receiver<(Req, oneshot::Sender<Resp>)>) -> Self {
+        Self::with_respond(rx, None)
+    }
+
+    /// Answer with `respond`, returning `None` for a request that should not be answered
+    pub fn with_fn<F>(rx: Receiver<(Req, oneshot::Sender<Resp>)>, respond: F) -> Self
+    where
+        F: FnMut(&Req) -> Option<Resp> + Send + 'static,
+    {
+        Self::with_respond(rx, Some(Box::new(respond)))
+    }
+
+    fn with_respond(
+        rx: Receiver<(Req, oneshot::Sender<Resp>)>,
+        respond: Option<Respond<Req, Resp>>,
+    ) -> Self {
+        let answers = Answers {
+            table: VecDeque::new(),
+            respond: respond.map(|respond| Arc::new(Mutex::new(respond))),
+            answered: 0,
+        };
+
+        Self {
+            rx: ScriptedReceiver::new(rx, answers),
+        }
+    }
+
+    /// Expect `request` next and answer it with `response`
+    pub fn expect(&mut self, request: Req, response: Resp) {
+        let description = format!("{:?}", request);
+        self.rx.script().table.push_back(Expected {
+            request,
+            response,
+            description,
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of expected requests not received yet
+    pub fn len(&self) -> usize {
+        self.rx.script().table.len()
+    }
+
+    /// Number of requests answered so far
+    pub fn answered(&self) -> usize {
+        self.rx.script().answered
+    }
+}
+
+impl<Req, Resp> fmt::Debug for Responder<Req, Resp>
+where
+    Req: fmt::Debug + PartialEq + Send + 'static,
+    Resp: Send + 'static,
+{
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.debug_struct("Responder").finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+    use super::*;
+    use crate::mock::sync::mpsc;
+
+    #[derive(Debug, PartialEq)]
+    enum Request {
+        Read(u16),
+        Write(u16, u16),
+    }
+
+    async fn call(
+        tx: &mpsc::Sender<(Request, oneshot::Sender<u16>)>,
+        request: Request,
+    ) -> Result<u16, oneshot::error::RecvError> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        tx.send((request, reply_tx)).await.unwrap();
+        reply_rx.await
+    }
+
+    #[test]
+    fn answers_table_in_order() {
+        let (tx, rx) = mpsc::channel(1);
+        let mut responder = Responder::new(rx);
+
+        responder.expect(Request::Read(1), 10);
+        responder.expect(Request::Write(1, 20), 20);
+
+        assert_ready_eq!(spawn(call(&tx, Request::Read(1))).poll(), Ok(10));
+        assert_ready_eq!(spawn(call(&tx, Request::Write(1, 20))).poll(), Ok(20));
+        assert!(responder.is_empty());
+        assert_eq!(responder.answered(), 2);
+    }
+
+    #[test]
+    fn falls_back_to_closure() {
+        let (tx, rx) = mpsc::channel(1);
+        let mut responder = Responder::with_fn(rx, |request: &Request| match request {
+            Request::Read(address) => Some(*address * 2),
+            Request::Write(..) => None,
+        });
+
+        responder.expect(Request::Read(1), 42);
+
+        assert_ready_eq!(spawn(call(&tx, Request::Read(1))).poll(), Ok(42));
+        assert_ready_eq!(spawn(call(&tx, Request::Read(4))).poll(), Ok(8));
+    }
+
+    #[test]
+    #[should_panic(expected = "request not answered: Write(1, 2)")]
+    fn panics_on_unanswered_request() {
+        let (tx, rx) = mpsc::channel(1);
+        let _responder = Responder::with_fn(rx, |_: &Request| None::<u16>);
+
+        let _ = spawn(call(&tx, Request::Write(1, 2))).poll();
+    }
+
+    #[test]
+    fn answers_requests_queued_before_creation() {
+        let (tx, rx) = mpsc::channel(1);
+        let mut reply = spawn(call(&tx, Request::Read(2)));
+
+        assert_pending!(reply.poll());
+        let responder = Responder::with_fn(rx, |request: &Request| match request {
+            Request::Read(address) => Some(*address * 2),
+            Request::Write(..) => None,
+        });
+        assert!(reply.is_woken());
+        assert_ready_eq!(reply.poll(), Ok(4));
+        assert_eq!(responder.answered(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "no more requests expected, received: Read(2)")]
+    fn reports_requests_queued_before_creation() {
+        let (tx, rx) = mpsc::channel(1);
+        let mut reply = spawn(call(&tx, Request::Read(2)));
+
+        assert_pending!(reply.poll());
+        let _responder = Responder::<Request, u16>::new(rx);
+    }
+
+    #[test]
+    #[should_panic(expected = "no write expected")]
+    fn panicking_closure_fails_the_sender() {
+        let (tx, rx) = mpsc::channel(1);
+        let _responder = Responder::with_fn(rx, |request: &Request| {
+            assert!(matches!(request, Request::Read(_)), "no write expected");
+            Some(0)
+        });
+
+        let _ = spawn(call(&tx, Request::Write(1, 2))).poll();
+    }
+
+    #[test]
+    fn closure_answers_after_a_panic() {
+        let (tx, rx) = mpsc::channel(1);
+        let responder = Responder::with_fn(rx, |request: &Request| match request {
+            Request::Read(address) => Some(*address * 2),
+            Request::Write(..) => panic!("no write expected"),
+        });
+
+        let failed = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            spawn(call(&tx, Request::Write(1, 2))).poll()
+        }));
+        assert!(failed.is_err());
+        assert_ready_eq!(spawn(call(&tx, Request::Read(3))).poll(), Ok(6));
+        assert_eq!(responder.answered(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "expected: Read(1),\n received: Read(2)")]
+    fn panics_on_unexpected_request() {
+        let (tx, rx) = mpsc::channel(1);
+        let mut responder = Responder::new(rx);
+
+        responder.expect(Request::Read(1), 10);
+        let _ = spawn(call(&tx, Request::Read(2))).poll();
+    }
+
+    #[test]
+    #[should_panic(expected = "responder contained 1 expected requests on drop: Read(1)")]
+    fn panics_on_drop_with_expected_requests() {
+        let (_tx, rx) = mpsc::channel::<(Request, oneshot::Sender<u16>)>(1);
+        let mut responder = Responder::new(rx);
+
+        responder.expect(Request::Read(1), 10);
+    }
+}
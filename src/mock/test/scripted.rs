@@ -0,0 +1,79 @@
+use crate::mock::sync::mpsc::{Intercept, Receiver};
+
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// Checks the messages sent on a channel taken over by a [`ScriptedReceiver`]
+pub(crate) trait Script: Send + 'static {
+    type Message;
+
+    /// Returns a description of the failure if `message` was not expected. The script isn't
+    /// locked by the caller, so that a panic while checking leaves it usable.
+    fn check(script: &Mutex<Self>, message: Self::Message) -> Result<(), String>;
+
+    /// Failure to report on drop if expected messages never came
+    fn unmet(&self) -> Option<String>;
+}
+
+impl<S> Intercept<S::Message> for Arc<Mutex<S>>
+where
+    S: Script,
+{
+    fn intercept(&mut self, message: S::Message) -> Result<(), String> {
+        S::check(self, message)
+    }
+}
+
+/// Receiving end of a mock mpsc channel whose messages are checked by a script as they are
+/// sent, shared by `ExpectReceiver` and `Responder`
+pub(crate) struct ScriptedReceiver<S>
+where
+    S: Script,
+{
+    rx: Receiver<S::Message>,
+    script: Arc<Mutex<S>>,
+}
+
+impl<S> ScriptedReceiver<S>
+where
+    S: Script,
+{
+    /// Messages already queued on `rx` are checked right away
+    pub(crate) fn new(rx: Receiver<S::Message>, script: S) -> Self {
+        let script = Arc::new(Mutex::new(script));
+        rx.intercept(Box::new(script.clone()));
+
+        Self { rx, script }
+    }
+
+    pub(crate) fn script(&self) -> MutexGuard<'_, S> {
+        self.script.lock().unwrap()
+    }
+
+    /// Panics with a failure no sender reported, such as one for a message held by a fault
+    /// policy until the clock released it
+    pub(crate) fn verify(&self) {
+        if let Some(err) = self.rx.take_rejected() {
+            panic!("{}", err);
+        }
+    }
+}
+
+impl<S> Drop for ScriptedReceiver<S>
+where
+    S: Script,
+{
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            return;
+        }
+
+        // delivers a message still kept back by a fault policy
+        self.rx.close();
+        self.verify();
+
+        let unmet = self.script().unmet();
+        if let Some(err) = unmet {
+            panic!("{}", err);
+        }
+    }
+}
@@ -1,32 +1,1109 @@
 use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::future::Future;
+use std::panic::Location;
+use std::pin::Pin;
+use std::sync::{Arc, Condvar, Mutex, OnceLock, Weak};
+use std::task::{Context, Poll, Waker};
 use std::time::Duration;
 
-thread_local!(static CLOCK: RefCell<Clock> = RefCell::new(Clock::new()));
+use super::{Instant, SystemTime};
 
-pub(crate) fn now() -> super::Instant {
-    CLOCK.with(|clock| clock.borrow().now())
+// clock used by the current thread, each thread starts with its own clock
+thread_local!(static CURRENT: RefCell<Clock> = RefCell::new(Clock::new()));
+
+// every clock starts at this instant, so that mock instants are reproducible
+static EPOCH: OnceLock<std::time::Instant> = OnceLock::new();
+
+pub(crate) fn epoch() -> std::time::Instant {
+    *EPOCH.get_or_init(std::time::Instant::now)
 }
 
-pub(crate) fn advance(duration: Duration) {
-    CLOCK.with(|clock| clock.borrow_mut().advance(duration));
+pub(crate) fn current() -> Clock {
+    CURRENT.with(|clock| clock.borrow().clone())
 }
 
-struct Clock {
-    now: std::time::Instant,
+pub(crate) fn now() -> Instant {
+    current().now()
+}
+
+const PPM: i128 = 1_000_000;
+
+/// Handle to a mock clock
+///
+/// Cloning the handle shares the same clock, which may be used from any thread.
+/// Each thread starts with its own clock; another one can be installed as the
+/// current clock with [`Clock::enter`] or [`Clock::bind`].
+///
+/// Clocks created with [`Clock::named`] share the timeline of the clock they were
+/// created from, but may run ahead, behind or drift relative to it. Advancing any
+/// of them advances all of them.
+#[derive(Clone)]
+pub struct Clock {
+    inner: Arc<Mutex<Inner>>,
+    skew: Skew,
+}
+
+// how a clock deviates from the reference time of its timeline
+#[derive(Clone, Default, PartialEq, Eq)]
+struct Skew {
+    name: Option<Arc<str>>,
+    offset_nanos: i128,
+    drift_ppm: i64,
 }
 
 impl Clock {
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Inner::new())),
+            skew: Skew::default(),
+        }
+    }
+
+    /// Create a named clock sharing this clock's timeline
+    ///
+    /// The new clock starts without offset or drift relative to the reference time of the
+    /// timeline, use [`Clock::ahead`], [`Clock::behind`] and [`Clock::drift_ppm`] to skew it.
+    pub fn named(&self, name: &str) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            skew: Skew {
+                name: Some(name.into()),
+                ..Skew::default()
+            },
+        }
+    }
+
+    /// Name given in [`Clock::named`]
+    pub fn name(&self) -> Option<&str> {
+        self.skew.name.as_deref()
+    }
+
+    /// Shift the clock ahead of the reference time
+    pub fn ahead(mut self, offset: Duration) -> Self {
+        self.skew.offset_nanos += offset.as_nanos() as i128;
+        self
+    }
+
+    /// Shift the clock behind the reference time
+    pub fn behind(mut self, offset: Duration) -> Self {
+        self.skew.offset_nanos -= offset.as_nanos() as i128;
+        self
+    }
+
+    /// Make the clock run faster (positive) or slower (negative) than the reference time,
+    /// in parts per million
+    pub fn drift_ppm(mut self, drift_ppm: i64) -> Self {
+        assert!(
+            i128::from(drift_ppm) > -PPM,
+            "a clock cannot drift by {} ppm",
+            drift_ppm
+        );
+        self.skew.drift_ppm = drift_ppm;
+        self
+    }
+
+    /// Returns a handle to the current clock of this thread
+    pub fn current() -> Self {
+        current()
+    }
+
+    /// Install this clock as the current clock until the guard is dropped
+    pub fn enter(&self) -> ClockGuard {
+        let previous = CURRENT.with(|clock| clock.replace(self.clone()));
+        ClockGuard {
+            previous: Some(previous),
+        }
+    }
+
+    /// Wrap a future so that this clock is the current clock whenever it is polled
+    pub fn bind<F: Future>(&self, future: F) -> WithClock<F> {
+        WithClock {
+            clock: self.clone(),
+            future,
+        }
+    }
+
+    pub fn now(&self) -> Instant {
+        let inner = self.inner.lock().unwrap();
+        self.skew.to_local(inner.origin, inner.now().into_std())
+    }
+
+    /// Wall-clock time of this clock
+    pub fn system_time(&self) -> SystemTime {
+        let inner = self.inner.lock().unwrap();
+        let local = self.skew.to_local(inner.origin, inner.now().into_std());
+        let elapsed = signed_nanos_since(inner.origin, local.into_std());
+        offset_system_time(inner.system_origin, elapsed)
+    }
+
+    /// Set the wall-clock time of this clock, the monotonic time is unaffected
+    pub fn set_system_time(&self, time: SystemTime) {
+        let mut inner = self.inner.lock().unwrap();
+        let local = self.skew.to_local(inner.origin, inner.now().into_std());
+        let elapsed = signed_nanos_since(inner.origin, local.into_std());
+        inner.system_origin = offset_system_time(time, -elapsed);
+    }
+
+    /// Step the wall-clock time forward, the monotonic time is unaffected
+    pub fn step_system_time_forward(&self, duration: Duration) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.system_origin += duration;
+    }
+
+    /// Step the wall-clock time backward, the monotonic time is unaffected
+    pub fn step_system_time_back(&self, duration: Duration) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.system_origin -= duration;
+    }
+
+    /// Move the time of this clock forward
+    ///
+    /// On a drifting clock the duration is measured in the clock's own time, so the
+    /// reference time moves by a shorter or longer duration.
+    ///
+    /// Actions scheduled with [`Clock::schedule_at`] run in order, each one at its own
+    /// instant, then the observers registered with [`Clock::on_advance`] are notified.
+    pub fn advance(&self, duration: Duration) {
+        self.advance_to(self.now() + duration);
+    }
+
+    // `duration` is measured in the reference time of the timeline
+    fn advance_reference(&self, duration: Duration) {
+        let start = self.inner.lock().unwrap().now();
+        let target = start + duration;
+
+        loop {
+            let (expired, action) = {
+                let mut inner = self.inner.lock().unwrap();
+                match inner.pop_scheduled(target) {
+                    Some((deadline, action)) => (inner.advance_to(deadline), Some(action)),
+                    None => (inner.advance_to(target), None),
+                }
+            };
+
+            // run outside of the lock so that woken tasks and actions may use the clock
+            for waker in expired {
+                waker.wake();
+            }
+            match action {
+                Some(action) => {
+                    let _guard = self.enter();
+                    action();
+                }
+                None => break,
+            }
+        }
+
+        self.notify_observers(start);
+    }
+
+    /// Register a callback invoked with the previous and the new time on each advance
+    pub fn on_advance<F>(&self, observer: F) -> ObserverId
+    where
+        F: FnMut(Instant, Instant) + Send + 'static,
+    {
+        let mut inner = self.inner.lock().unwrap();
+        let id = inner.next_id();
+        inner.observers.push(Observer {
+            id,
+            skew: self.skew.clone(),
+            callback: Arc::new(Mutex::new(observer)),
+        });
+        ObserverId(id)
+    }
+
+    /// Unregister a callback added with [`Clock::on_advance`]
+    pub fn remove_observer(&self, id: ObserverId) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.observers.retain(|observer| observer.id != id.0);
+    }
+
+    /// Run `action` once the time reaches `instant`
+    ///
+    /// The action runs with the time set to `instant`, even if the clock is advanced past it.
+    pub fn schedule_at<F>(&self, instant: Instant, action: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let mut inner = self.inner.lock().unwrap();
+        let instant = self.to_base(&inner, instant);
+        let id = inner.next_id();
+        inner.scheduled.insert((instant, id), Box::new(action));
+        inner.notify_driver();
+    }
+
+    fn notify_observers(&self, start: Instant) {
+        let (origin, end, observers) = {
+            let inner = self.inner.lock().unwrap();
+            (inner.origin, inner.now(), inner.observers.clone())
+        };
+
+        let _guard = self.enter();
+        for observer in observers {
+            // an observer advancing the clock is not notified of its own advance
+            if let Ok(mut callback) = observer.callback.try_lock() {
+                callback(
+                    observer.skew.to_local(origin, start.into_std()),
+                    observer.skew.to_local(origin, end.into_std()),
+                );
+            }
+        }
+    }
+
+    pub fn advance_to(&self, deadline: Instant) {
+        let duration = {
+            let inner = self.inner.lock().unwrap();
+            self.to_base(&inner, deadline)
+                .saturating_duration_since(inner.now())
+        };
+        self.advance_reference(duration);
+    }
+
+    pub fn next_deadline(&self) -> Option<Instant> {
+        let inner = self.inner.lock().unwrap();
+        inner
+            .next_deadline()
+            .map(|deadline| self.skew.to_local(inner.origin, deadline.into_std()))
+    }
+
+    /// Let the time run on its own, `factor` times faster than the real time
+    ///
+    /// Timers fire from a background thread as the accelerated time reaches their
+    /// deadline. The time may still be moved forward with [`Clock::advance`].
+    pub fn accelerate(&self, factor: f64) {
+        assert!(
+            factor.is_finite() && factor > 0.0,
+            "invalid acceleration factor {}",
+            factor
+        );
+
+        let mut inner = self.inner.lock().unwrap();
+        inner.now = inner.now().into_std();
+        inner.acceleration = Some(Acceleration {
+            real_start: std::time::Instant::now(),
+            factor,
+        });
+
+        match &inner.driver {
+            Some(driver) => driver.notify_all(),
+            None => {
+                let driver = Arc::new(Condvar::new());
+                inner.driver = Some(driver.clone());
+                let timeline = Arc::downgrade(&self.inner);
+                std::thread::spawn(move || drive(timeline, driver));
+            }
+        }
+    }
+
+    /// Stop the time from running on its own, it only moves with [`Clock::advance`]
+    pub fn pause(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.now = inner.now().into_std();
+        inner.acceleration = None;
+        if let Some(driver) = inner.driver.take() {
+            driver.notify_all();
+        }
+    }
+
+    /// Returns `true` if the time runs on its own, see [`Clock::accelerate`]
+    pub fn is_accelerated(&self) -> bool {
+        self.inner.lock().unwrap().acceleration.is_some()
+    }
+
+    pub fn set_auto_advance(&self, enabled: bool) {
+        self.inner.lock().unwrap().auto_advance = enabled;
+    }
+
+    pub fn is_auto_advance(&self) -> bool {
+        self.inner.lock().unwrap().auto_advance
+    }
+
+    /// Live timers that have not elapsed yet, ordered by deadline
+    pub fn pending_timers(&self) -> Vec<PendingTimer> {
+        let inner = self.inner.lock().unwrap();
+        let now = inner.now();
+        let mut timers: Vec<_> = inner
+            .live
+            .iter()
+            .filter(|(_, timer)| timer.deadline > now)
+            .map(|(id, timer)| (timer.deadline, *id, timer.location))
+            .collect();
+        timers.sort();
+
+        timers
+            .into_iter()
+            .map(|(deadline, _, location)| PendingTimer {
+                deadline: self.skew.to_local(inner.origin, deadline.into_std()),
+                location,
+            })
+            .collect()
+    }
+
+    pub(crate) fn insert_timer(
+        &self,
+        deadline: Instant,
+        location: &'static Location<'static>,
+    ) -> u64 {
+        let mut inner = self.inner.lock().unwrap();
+        let deadline = self.to_base(&inner, deadline);
+        inner.insert_timer(deadline, location)
+    }
+
+    pub(crate) fn remove_timer(&self, deadline: Instant, id: u64) {
+        let mut inner = self.inner.lock().unwrap();
+        let deadline = self.to_base(&inner, deadline);
+        inner.deregister(deadline, id);
+        inner.live.remove(&id);
+    }
+
+    pub(crate) fn is_elapsed(&self, deadline: Instant) -> bool {
+        let inner = self.inner.lock().unwrap();
+        inner.now() >= self.to_base(&inner, deadline)
+    }
+
+    pub(crate) fn register(&self, deadline: Instant, id: u64, waker: &Waker) {
+        let mut inner = self.inner.lock().unwrap();
+        let deadline = self.to_base(&inner, deadline);
+        inner.register(deadline, id, waker);
+    }
+
+    pub(crate) fn reset(&self, deadline: Instant, new_deadline: Instant, id: u64) {
+        let mut inner = self.inner.lock().unwrap();
+        let deadline = self.to_base(&inner, deadline);
+        let new_deadline = self.to_base(&inner, new_deadline);
+        inner.reset(deadline, new_deadline, id);
+    }
+
+    pub(crate) fn deregister(&self, deadline: Instant, id: u64) {
+        let mut inner = self.inner.lock().unwrap();
+        let deadline = self.to_base(&inner, deadline);
+        inner.deregister(deadline, id);
+    }
+
+    /// Returns `true` if both handles refer to the same clock
+    pub fn same_clock(&self, other: &Clock) -> bool {
+        Arc::ptr_eq(&self.inner, &other.inner) && self.skew == other.skew
+    }
+
+    /// Returns `true` if both clocks advance together
+    pub fn same_timeline(&self, other: &Clock) -> bool {
+        Arc::ptr_eq(&self.inner, &other.inner)
+    }
+
+    // timers are registered in the reference time of the timeline
+    fn to_base(&self, inner: &Inner, deadline: Instant) -> Instant {
+        self.skew.to_base(inner.origin, deadline)
+    }
+}
+
+impl Skew {
+    fn to_local(&self, origin: std::time::Instant, base: std::time::Instant) -> Instant {
+        if *self == Skew::default() {
+            return base.into();
+        }
+
+        // round down so that `to_local(to_base(t)) >= t`
+        let elapsed = (base - origin).as_nanos() as i128;
+        let local =
+            (elapsed * (PPM + i128::from(self.drift_ppm))).div_euclid(PPM) + self.offset_nanos;
+        offset_from(origin, local)
+    }
+
+    fn to_base(&self, origin: std::time::Instant, local: Instant) -> Instant {
+        if *self == Skew::default() {
+            return local;
+        }
+
+        let local = signed_nanos_since(origin, local.into_std()) - self.offset_nanos;
+        if local <= 0 {
+            return origin.into();
+        }
+
+        // round up so that a timer never fires before its local deadline
+        let scale = PPM + i128::from(self.drift_ppm);
+        let elapsed = (local * PPM + scale - 1) / scale;
+        offset_from(origin, elapsed)
+    }
+}
+
+fn signed_nanos_since(origin: std::time::Instant, instant: std::time::Instant) -> i128 {
+    match instant.checked_duration_since(origin) {
+        Some(elapsed) => elapsed.as_nanos() as i128,
+        None => -((origin - instant).as_nanos() as i128),
+    }
+}
+
+fn offset_system_time(time: SystemTime, nanos: i128) -> SystemTime {
+    let duration = Duration::from_nanos(nanos.unsigned_abs() as u64);
+    if nanos >= 0 {
+        time + duration
+    } else {
+        time - duration
+    }
+}
+
+fn offset_from(origin: std::time::Instant, nanos: i128) -> Instant {
+    let duration = Duration::from_nanos(nanos.unsigned_abs() as u64);
+    let instant = if nanos >= 0 {
+        origin.checked_add(duration)
+    } else {
+        origin.checked_sub(duration)
+    };
+    instant
+        .expect("clock offset is out of the range of Instant")
+        .into()
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for Clock {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut fmt = fmt.debug_struct("Clock");
+        if let Some(name) = self.name() {
+            fmt.field("name", &name);
+        }
+        fmt.field("now", &self.now()).finish()
+    }
+}
+
+// Fires timers of an accelerated timeline as the time passes
+fn drive(timeline: Weak<Mutex<Inner>>, driver: Arc<Condvar>) {
+    // upper bound on how long to wait, so that the thread notices when the clock is dropped
+    const MAX_WAIT: Duration = Duration::from_millis(100);
+
+    while let Some(timeline) = timeline.upgrade() {
+        let mut inner = timeline.lock().unwrap();
+        let factor = match &inner.acceleration {
+            Some(acceleration) if is_driver(&inner, &driver) => acceleration.factor,
+            _ => return,
+        };
+
+        let expired = inner.expire();
+        let now = inner.now();
+        let action = inner.pop_scheduled(now).map(|(_, action)| action);
+        if !expired.is_empty() || action.is_some() {
+            drop(inner);
+            for waker in expired {
+                waker.wake();
+            }
+            if let Some(action) = action {
+                let _guard = Clock {
+                    inner: timeline.clone(),
+                    skew: Skew::default(),
+                }
+                .enter();
+                action();
+            }
+            continue;
+        }
+
+        let wait = match inner.next_deadline() {
+            Some(deadline) => deadline
+                .saturating_duration_since(inner.now())
+                .div_f64(factor)
+                .min(MAX_WAIT),
+            None => MAX_WAIT,
+        };
+        let _ = driver.wait_timeout(inner, wait).unwrap();
+    }
+}
+
+// a new driver replaces this one if the clock was paused and accelerated again
+fn is_driver(inner: &Inner, driver: &Arc<Condvar>) -> bool {
+    inner
+        .driver
+        .as_ref()
+        .is_some_and(|current| Arc::ptr_eq(current, driver))
+}
+
+/// Identifies a callback registered with [`Clock::on_advance`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ObserverId(u64);
+
+type ObserverCallback = Arc<Mutex<dyn FnMut(Instant, Instant) + Send>>;
+
+#[derive(Clone)]
+struct Observer {
+    id: u64,
+    skew: Skew,
+    callback: ObserverCallback,
+}
+
+type ScheduledAction = Box<dyn FnOnce() + Send>;
+
+/// Timer that has not elapsed yet, see [`Clock::pending_timers`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PendingTimer {
+    deadline: Instant,
+    location: &'static Location<'static>,
+}
+
+impl PendingTimer {
+    /// When the timer fires, on the clock that listed it
+    pub fn deadline(&self) -> Instant {
+        self.deadline
+    }
+
+    /// Where the timer was created
+    pub fn location(&self) -> &'static Location<'static> {
+        self.location
+    }
+}
+
+// e.g. `src/proto.rs:120 fires at +5.000s`
+impl fmt::Display for PendingTimer {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            fmt,
+            "{}:{} fires at ",
+            self.location.file(),
+            self.location.line()
+        )?;
+        self.deadline.fmt_since_epoch(fmt)
+    }
+}
+
+/// Restores the previous current clock when dropped
+#[must_use = "the clock is only current until the guard is dropped"]
+pub struct ClockGuard {
+    previous: Option<Clock>,
+}
+
+impl Drop for ClockGuard {
+    fn drop(&mut self) {
+        if let Some(previous) = self.previous.take() {
+            // the thread-local may already be destroyed during thread teardown
+            let _ = CURRENT.try_with(|clock| clock.replace(previous));
+        }
+    }
+}
+
+impl fmt::Debug for ClockGuard {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.debug_struct("ClockGuard").finish()
+    }
+}
+
+/// Future returned by [`Clock::bind`]
+#[derive(Debug)]
+pub struct WithClock<F> {
+    clock: Clock,
+    future: F,
+}
+
+impl<F: Future> Future for WithClock<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // Safety: `future` is never moved out of the pinned `WithClock`
+        let this = unsafe { self.get_unchecked_mut() };
+        let future = unsafe { Pin::new_unchecked(&mut this.future) };
+
+        let _guard = this.clock.enter();
+        future.poll(cx)
+    }
+}
+
+struct Inner {
+    origin: std::time::Instant,
+    // manually advanced time, the accelerated time is added on top of it
+    now: std::time::Instant,
+    acceleration: Option<Acceleration>,
+    // wakes the thread that fires timers while the time is accelerated
+    driver: Option<Arc<Condvar>>,
+    // wall-clock time at `origin`
+    system_origin: SystemTime,
+    next_id: u64,
+    auto_advance: bool,
+    // every live timer, whether or not a task is waiting on it
+    live: BTreeMap<u64, LiveTimer>,
+    // registered timers ordered by deadline, then by creation order
+    timers: BTreeMap<(Instant, u64), Waker>,
+    observers: Vec<Observer>,
+    scheduled: BTreeMap<(Instant, u64), ScheduledAction>,
+}
+
+struct Acceleration {
+    real_start: std::time::Instant,
+    factor: f64,
+}
+
+struct LiveTimer {
+    deadline: Instant,
+    location: &'static Location<'static>,
+}
+
+impl Inner {
     fn new() -> Self {
+        let origin = epoch();
         Self {
-            now: std::time::Instant::now(),
+            origin,
+            now: origin,
+            acceleration: None,
+            driver: None,
+            system_origin: std::time::SystemTime::now().into(),
+            next_id: 0,
+            auto_advance: false,
+            live: BTreeMap::new(),
+            timers: BTreeMap::new(),
+            observers: Vec::new(),
+            scheduled: BTreeMap::new(),
+        }
+    }
+
+    fn now(&self) -> Instant {
+        match &self.acceleration {
+            Some(acceleration) => {
+                let elapsed = acceleration
+                    .real_start
+                    .elapsed()
+                    .mul_f64(acceleration.factor);
+                (self.now + elapsed).into()
+            }
+            None => self.now.into(),
+        }
+    }
+
+    fn notify_driver(&self) {
+        if let Some(driver) = &self.driver {
+            driver.notify_all();
+        }
+    }
+
+    fn advance_to(&mut self, instant: Instant) -> Vec<Waker> {
+        self.now += instant.saturating_duration_since(self.now());
+        self.expire()
+    }
+
+    fn next_id(&mut self) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    fn next_scheduled(&self) -> Option<Instant> {
+        self.scheduled.keys().next().map(|(instant, _)| *instant)
+    }
+
+    // earliest action scheduled at or before `limit`
+    fn pop_scheduled(&mut self, limit: Instant) -> Option<(Instant, ScheduledAction)> {
+        let entry = self.scheduled.first_entry()?;
+        if entry.key().0 > limit {
+            return None;
+        }
+        let ((instant, _), action) = entry.remove_entry();
+        Some((instant, action))
+    }
+
+    // earliest instant at which a timer fires or a scheduled action runs
+    fn next_deadline(&self) -> Option<Instant> {
+        let next_timer = self.timers.keys().next().map(|(deadline, _)| *deadline);
+        next_timer.into_iter().chain(self.next_scheduled()).min()
+    }
+
+    fn insert_timer(&mut self, deadline: Instant, location: &'static Location<'static>) -> u64 {
+        let id = self.next_id();
+        self.live.insert(id, LiveTimer { deadline, location });
+        id
+    }
+
+    fn register(&mut self, deadline: Instant, id: u64, waker: &Waker) {
+        match self.timers.get_mut(&(deadline, id)) {
+            Some(existing) if existing.will_wake(waker) => {}
+            Some(existing) => *existing = waker.clone(),
+            None => {
+                self.timers.insert((deadline, id), waker.clone());
+                self.notify_driver();
+            }
+        }
+    }
+
+    fn reset(&mut self, deadline: Instant, new_deadline: Instant, id: u64) {
+        if let Some(timer) = self.live.get_mut(&id) {
+            timer.deadline = new_deadline;
+        }
+        if let Some(waker) = self.timers.remove(&(deadline, id)) {
+            self.timers.insert((new_deadline, id), waker);
+            self.notify_driver();
+        }
+    }
+
+    fn deregister(&mut self, deadline: Instant, id: u64) {
+        self.timers.remove(&(deadline, id));
+    }
+
+    fn expire(&mut self) -> Vec<Waker> {
+        let now = self.now();
+        let mut expired = Vec::new();
+        while let Some(entry) = self.timers.first_entry() {
+            if entry.key().0 > now {
+                break;
+            }
+            expired.push(entry.remove());
+        }
+        expired
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+    use crate::mock::test::*;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn clocks_are_isolated() {
+        let first = Clock::new();
+        let second = Clock::new();
+        let second_start = second.now();
+
+        first.advance(Duration::from_secs(1));
+        assert_eq!(second.now(), second_start);
+    }
+
+    #[test]
+    fn enter_installs_current_clock() {
+        let clock = Clock::new();
+        let start = clock.now();
+
+        {
+            let _guard = clock.enter();
+            advance(Duration::from_secs(1));
+            assert_eq!(Instant::now(), start + Duration::from_secs(1));
         }
+
+        assert!(!Clock::current().same_clock(&clock));
+        assert_eq!(clock.now(), start + Duration::from_secs(1));
+    }
+
+    #[test]
+    fn sleep_resolves_against_its_clock() {
+        let clock = Clock::new();
+        let sleep = {
+            let _guard = clock.enter();
+            sleep(Duration::from_secs(1))
+        };
+        let mut task = spawn(sleep);
+
+        assert_pending!(task.poll());
+        advance(Duration::from_secs(1));
+        assert!(!task.is_woken());
+        clock.advance(Duration::from_secs(1));
+        assert!(task.is_woken());
+        assert_ready!(task.poll());
     }
 
-    pub fn now(&self) -> super::Instant {
-        self.now.into()
+    #[test]
+    fn spawn_with_clock() {
+        let clock = Clock::new();
+        let start = clock.now();
+        let mut task = spawn(async { Instant::now() }).with_clock(clock.clone());
+
+        clock.advance(Duration::from_secs(1));
+        assert_ready_eq!(task.poll(), start + Duration::from_secs(1));
     }
 
-    pub fn advance(&mut self, duration: Duration) {
-        self.now += duration;
+    #[test]
+    fn shared_across_threads() {
+        let clock = Clock::new();
+        let start = clock.now();
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .build()
+            .unwrap();
+
+        let task = runtime.spawn(clock.bind(async {
+            sleep(Duration::from_secs(1)).await;
+            Instant::now()
+        }));
+
+        while clock.next_deadline().is_none() {
+            std::thread::yield_now();
+        }
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(
+            runtime.block_on(task).unwrap(),
+            start + Duration::from_secs(1)
+        );
+    }
+
+    #[test]
+    fn named_clocks_advance_together() {
+        let reference = Clock::new();
+        let start = reference.now();
+        let host_a = reference.named("host A").ahead(Duration::from_secs(10));
+        let host_b = reference.named("host B").behind(Duration::from_secs(5));
+
+        assert_eq!(host_a.name(), Some("host A"));
+        assert_eq!(host_a.now(), start + Duration::from_secs(10));
+        assert_eq!(host_b.now(), start - Duration::from_secs(5));
+
+        host_a.advance(Duration::from_secs(1));
+        assert_eq!(reference.now(), start + Duration::from_secs(1));
+        assert_eq!(host_a.now(), start + Duration::from_secs(11));
+        assert_eq!(host_b.now(), start - Duration::from_secs(4));
+        assert!(host_a.same_timeline(&host_b));
+        assert!(!host_a.same_clock(&host_b));
+    }
+
+    #[test]
+    fn drifting_clock() {
+        let reference = Clock::new();
+        let start = reference.now();
+        let fast = reference.named("fast").drift_ppm(1000);
+        let slow = reference.named("slow").drift_ppm(-1000);
+
+        reference.advance(Duration::from_secs(1000));
+        assert_eq!(fast.now(), start + Duration::from_secs(1001));
+        assert_eq!(slow.now(), start + Duration::from_secs(999));
+    }
+
+    #[test]
+    fn advance_drifting_clock_in_its_own_time() {
+        let reference = Clock::new();
+        let start = reference.now();
+        let slow = reference.named("slow").drift_ppm(-500_000);
+
+        slow.advance(Duration::from_secs(1));
+        assert_eq!(slow.now(), start + Duration::from_secs(1));
+        assert_eq!(reference.now(), start + Duration::from_secs(2));
+    }
+
+    #[test]
+    fn sleep_resolves_against_skewed_clock() {
+        let reference = Clock::new();
+        let slow = reference.named("slow").drift_ppm(-500_000);
+        let mut task =
+            spawn(async { sleep(Duration::from_secs(1)).await }).with_clock(slow.clone());
+
+        assert_pending!(task.poll());
+        assert_eq!(
+            reference.next_deadline(),
+            Some(reference.now() + Duration::from_secs(2))
+        );
+        assert_eq!(
+            slow.next_deadline(),
+            Some(slow.now() + Duration::from_secs(1))
+        );
+        reference.advance(Duration::from_secs(1));
+        assert!(!task.is_woken());
+        reference.advance(Duration::from_secs(1));
+        assert!(task.is_woken());
+        assert_ready!(task.poll());
+    }
+
+    #[test]
+    fn sleep_on_clock_ahead_and_behind() {
+        let reference = Clock::new();
+        let ahead = reference.named("ahead").ahead(Duration::from_secs(60));
+        let behind = reference.named("behind").behind(Duration::from_secs(60));
+
+        let mut ahead_task =
+            spawn(async { sleep(Duration::from_secs(1)).await }).with_clock(ahead.clone());
+        let mut behind_task =
+            spawn(async { sleep(Duration::from_secs(2)).await }).with_clock(behind.clone());
+
+        assert_pending!(ahead_task.poll());
+        assert_pending!(behind_task.poll());
+        ahead.advance(Duration::from_secs(1));
+        assert_ready!(ahead_task.poll());
+        assert_pending!(behind_task.poll());
+        behind.advance(Duration::from_secs(1));
+        assert_ready!(behind_task.poll());
+    }
+
+    #[test]
+    fn pending_timers_list_creation_sites() {
+        let start = Instant::now();
+        let early = sleep(Duration::from_secs(1));
+        let line = line!() - 1;
+        let late = sleep_until(start + Duration::from_secs(5));
+        let _elapsed = sleep(Duration::from_secs(0));
+
+        let timers = pending_timers();
+        assert_eq!(timers.len(), 2);
+        assert_eq!(timers[0].deadline(), start + Duration::from_secs(1));
+        assert_eq!(timers[0].location().file(), file!());
+        assert_eq!(timers[0].location().line(), line);
+        assert_eq!(
+            timers[1].to_string(),
+            format!("{}:{} fires at +5.000s", file!(), line + 2)
+        );
+
+        drop(early);
+        advance(Duration::from_secs(5));
+        assert!(pending_timers().is_empty());
+        drop(late);
+    }
+
+    #[test]
+    fn pending_timers_follow_reset() {
+        let start = Instant::now();
+        let mut sleep = Box::pin(sleep(Duration::from_secs(1)));
+
+        sleep.as_mut().reset(start + Duration::from_secs(3));
+        let timers = pending_timers();
+        assert_eq!(timers.len(), 1);
+        assert_eq!(timers[0].deadline(), start + Duration::from_secs(3));
+    }
+
+    #[test]
+    fn accelerated_time_runs_on_its_own() {
+        let clock = Clock::new();
+        let start = clock.now();
+
+        clock.accelerate(1000.0);
+        std::thread::sleep(Duration::from_millis(10));
+        assert!(clock.now() - start >= Duration::from_secs(10));
+
+        clock.pause();
+        let paused = clock.now();
+        std::thread::sleep(Duration::from_millis(10));
+        assert_eq!(clock.now(), paused);
+        assert!(!clock.is_accelerated());
+    }
+
+    #[test]
+    fn advance_while_accelerated() {
+        let clock = Clock::new();
+        let start = clock.now();
+
+        clock.accelerate(1.0);
+        clock.advance(Duration::from_secs(3600));
+        assert!(clock.now() - start >= Duration::from_secs(3600));
+        assert!(clock.now() - start < Duration::from_secs(3660));
+    }
+
+    #[test]
+    fn accelerated_sleep_fires_on_runtime() {
+        let clock = Clock::new();
+        let start = clock.now();
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+
+        clock.accelerate(10.0 * 24.0 * 3600.0);
+        let elapsed = runtime.block_on(clock.bind(async {
+            // a day of polling every hour
+            let mut interval = interval(Duration::from_secs(3600));
+            for _ in 0..=24 {
+                interval.tick().await;
+            }
+            Instant::now() - start
+        }));
+        assert!(elapsed >= Duration::from_secs(24 * 3600));
+    }
+
+    #[test]
+    fn observers_see_each_advance() {
+        let clock = Clock::new();
+        let start = clock.now();
+        let ahead = clock.named("ahead").ahead(Duration::from_secs(10));
+        let seen = Arc::new(Mutex::new(Vec::new()));
+
+        let id = {
+            let seen = seen.clone();
+            ahead.on_advance(move |old, new| seen.lock().unwrap().push((old, new)))
+        };
+        clock.advance(Duration::from_secs(1));
+        clock.advance(Duration::from_secs(2));
+        clock.remove_observer(id);
+        clock.advance(Duration::from_secs(3));
+
+        let ahead_start = start + Duration::from_secs(10);
+        assert_eq!(
+            *seen.lock().unwrap(),
+            vec![
+                (ahead_start, ahead_start + Duration::from_secs(1)),
+                (
+                    ahead_start + Duration::from_secs(1),
+                    ahead_start + Duration::from_secs(3)
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn scheduled_actions_run_at_their_instant() {
+        let start = Instant::now();
+        let seen = Arc::new(Mutex::new(Vec::new()));
+
+        for secs in [5, 2] {
+            let seen = seen.clone();
+            schedule_at(start + Duration::from_secs(secs), move || {
+                seen.lock().unwrap().push(Instant::now())
+            });
+        }
+        advance(Duration::from_secs(1));
+        assert!(seen.lock().unwrap().is_empty());
+        advance(Duration::from_secs(9));
+
+        assert_eq!(
+            *seen.lock().unwrap(),
+            vec![
+                start + Duration::from_secs(2),
+                start + Duration::from_secs(5)
+            ]
+        );
+        assert_eq!(Instant::now(), start + Duration::from_secs(10));
+    }
+
+    #[test]
+    fn scheduled_action_wakes_timers_in_order() {
+        let start = Instant::now();
+        let mut task = spawn(sleep(Duration::from_secs(1)));
+        let (tx, mut rx) = crate::mock::sync::mpsc::unbounded_channel();
+
+        assert_pending!(task.poll());
+        schedule_at(start + Duration::from_secs(2), move || {
+            tx.send(Instant::now()).unwrap();
+        });
+        advance(Duration::from_secs(3));
+
+        assert!(task.is_woken());
+        assert_eq!(rx.try_recv(), Ok(start + Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn next_deadline_includes_scheduled_actions() {
+        let start = Instant::now();
+        let mut task = spawn(sleep(Duration::from_secs(3)));
+
+        assert_pending!(task.poll());
+        schedule_at(start + Duration::from_secs(1), || {});
+        assert_eq!(next_deadline(), Some(start + Duration::from_secs(1)));
+        advance(Duration::from_secs(1));
+        assert_eq!(next_deadline(), Some(start + Duration::from_secs(3)));
+    }
+
+    #[test]
+    fn auto_advance_runs_scheduled_actions() {
+        let start = Instant::now();
+        let (tx, mut rx) = crate::mock::sync::mpsc::unbounded_channel();
+        let mut task = spawn(async move { rx.recv().await });
+
+        schedule_at(start + Duration::from_secs(2), move || {
+            tx.send(Instant::now()).unwrap();
+        });
+        set_auto_advance(true);
+        assert_ready_eq!(task.poll(), Some(start + Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn scheduled_actions_run_while_accelerated() {
+        let clock = Clock::new();
+        let (tx, rx) = std::sync::mpsc::channel();
+
+        clock.schedule_at(clock.now() + Duration::from_secs(60), move || {
+            tx.send(()).unwrap();
+        });
+        clock.accelerate(60_000.0);
+        rx.recv_timeout(Duration::from_secs(5)).unwrap();
     }
 }
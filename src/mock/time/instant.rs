@@ -95,8 +95,65 @@ impl ops::SubAssign<Duration> for Instant {
     }
 }
 
+// Shown as the time elapsed since the clock epoch, e.g. `Instant(+1.250s)`
 impl fmt::Debug for Instant {
     fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
-        self.std.fmt(fmt)
+        write!(fmt, "Instant(")?;
+        self.fmt_since_epoch(fmt)?;
+        write!(fmt, ")")
+    }
+}
+
+impl Instant {
+    pub(crate) fn fmt_since_epoch(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let epoch = clock::epoch();
+        let (sign, elapsed) = match self.std.checked_duration_since(epoch) {
+            Some(elapsed) => ('+', elapsed),
+            None => ('-', epoch - self.std),
+        };
+
+        let nanos = elapsed.subsec_nanos();
+        if nanos % 1_000_000 == 0 {
+            write!(
+                fmt,
+                "{}{}.{:03}s",
+                sign,
+                elapsed.as_secs(),
+                nanos / 1_000_000
+            )
+        } else {
+            write!(fmt, "{}{}.{:09}s", sign, elapsed.as_secs(), nanos)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+
+    #[test]
+    fn clocks_start_at_epoch() {
+        assert_eq!(Clock::new().now(), Clock::new().now());
+        assert_eq!(elapsed_since_start(), Duration::from_secs(0));
+        assert_eq!(format!("{:?}", Instant::now()), "Instant(+0.000s)");
+    }
+
+    #[test]
+    fn debug_shows_elapsed_since_epoch() {
+        advance(Duration::from_millis(1250));
+        assert_eq!(elapsed_since_start(), Duration::from_millis(1250));
+        assert_eq!(format!("{:?}", Instant::now()), "Instant(+1.250s)");
+        assert_eq!(
+            format!("{:?}", Instant::now() + Duration::from_nanos(1)),
+            "Instant(+1.250000001s)"
+        );
+    }
+
+    #[test]
+    fn debug_before_epoch() {
+        let behind = Clock::current()
+            .named("behind")
+            .behind(Duration::from_millis(500));
+        assert_eq!(format!("{:?}", behind.now()), "Instant(-0.500s)");
     }
 }
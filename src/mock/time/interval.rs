@@ -0,0 +1,199 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use super::{Instant, Sleep};
+
+#[track_caller]
+pub fn interval(period: Duration) -> Interval {
+    assert!(period > Duration::new(0, 0), "`period` must be non-zero.");
+
+    interval_at(Instant::now(), period)
+}
+
+#[track_caller]
+pub fn interval_at(start: Instant, period: Duration) -> Interval {
+    assert!(period > Duration::new(0, 0), "`period` must be non-zero.");
+
+    Interval {
+        delay: Box::pin(Sleep::new_deadline(start)),
+        period,
+        missed_tick_behavior: MissedTickBehavior::default(),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MissedTickBehavior {
+    /// Ticks as fast as possible until caught up.
+    #[default]
+    Burst,
+
+    /// Ticks at multiples of `period` from when `tick` was called, rather than from `start`.
+    Delay,
+
+    /// Skips missed ticks and ticks on the next multiple of `period` from `start`.
+    Skip,
+}
+
+impl MissedTickBehavior {
+    fn next_timeout(&self, timeout: Instant, now: Instant, period: Duration) -> Instant {
+        match self {
+            Self::Burst => timeout + period,
+            Self::Delay => now + period,
+            Self::Skip => {
+                let since = (now - timeout).as_nanos();
+                let period_nanos = period.as_nanos();
+                now + period - Duration::from_nanos((since % period_nanos) as u64)
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Interval {
+    delay: Pin<Box<Sleep>>,
+    period: Duration,
+    missed_tick_behavior: MissedTickBehavior,
+}
+
+impl Interval {
+    pub async fn tick(&mut self) -> Instant {
+        std::future::poll_fn(|cx| self.poll_tick(cx)).await
+    }
+
+    pub fn poll_tick(&mut self, cx: &mut Context<'_>) -> Poll<Instant> {
+        if self.delay.as_mut().poll(cx).is_pending() {
+            return Poll::Pending;
+        }
+
+        let timeout = self.delay.deadline();
+        let now = Instant::now();
+
+        // same tolerance as tokio before a tick is considered missed
+        let next = if now > timeout + Duration::from_millis(5) {
+            self.missed_tick_behavior
+                .next_timeout(timeout, now, self.period)
+        } else {
+            timeout + self.period
+        };
+
+        self.delay.as_mut().reset(next);
+
+        Poll::Ready(timeout)
+    }
+
+    pub fn reset(&mut self) {
+        self.delay.as_mut().reset(Instant::now() + self.period);
+    }
+
+    pub fn missed_tick_behavior(&self) -> MissedTickBehavior {
+        self.missed_tick_behavior
+    }
+
+    pub fn set_missed_tick_behavior(&mut self, behavior: MissedTickBehavior) {
+        self.missed_tick_behavior = behavior;
+    }
+
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+    use crate::mock::test::*;
+
+    const PERIOD: Duration = Duration::from_secs(1);
+
+    fn ticks_after_ten_periods(behavior: MissedTickBehavior) -> (usize, Instant) {
+        let start = Instant::now();
+        let mut interval = interval(PERIOD);
+        interval.set_missed_tick_behavior(behavior);
+
+        assert_ready_eq!(spawn(interval.tick()).poll(), start);
+        advance(PERIOD * 10);
+
+        let mut count = 0;
+        while spawn(interval.tick()).poll().is_ready() {
+            count += 1;
+        }
+
+        // when the following tick is scheduled
+        advance(PERIOD * 10);
+        let next = assert_ready!(spawn(interval.tick()).poll());
+        (count, next)
+    }
+
+    #[test]
+    fn first_tick_is_immediate() {
+        let mut interval = interval(PERIOD);
+        let mut task = spawn(async { interval.tick().await });
+
+        assert_ready!(task.poll());
+    }
+
+    #[test]
+    fn ticks_every_period() {
+        let start = Instant::now();
+        let mut interval = interval_at(start + PERIOD, PERIOD);
+
+        let mut task = spawn(interval.tick());
+        assert_pending!(task.poll());
+        advance(PERIOD);
+        assert!(task.is_woken());
+        assert_ready_eq!(task.poll(), start + PERIOD);
+        drop(task);
+
+        assert_pending!(spawn(interval.tick()).poll());
+        advance(PERIOD);
+        assert_ready_eq!(spawn(interval.tick()).poll(), start + PERIOD * 2);
+    }
+
+    #[test]
+    fn burst_catches_up_on_missed_ticks() {
+        let start = Instant::now();
+        let (count, next) = ticks_after_ten_periods(MissedTickBehavior::Burst);
+
+        assert_eq!(count, 10);
+        assert_eq!(next, start + PERIOD * 11);
+    }
+
+    #[test]
+    fn delay_ticks_once_and_restarts_period() {
+        let start = Instant::now();
+        let (count, next) = ticks_after_ten_periods(MissedTickBehavior::Delay);
+
+        assert_eq!(count, 1);
+        assert_eq!(next, start + PERIOD * 11);
+    }
+
+    #[test]
+    fn skip_ticks_once_and_stays_aligned() {
+        let start = Instant::now();
+        let mut interval = interval(PERIOD);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+        assert_ready!(spawn(interval.tick()).poll());
+        advance(PERIOD * 10 + PERIOD / 2);
+        assert_ready_eq!(spawn(interval.tick()).poll(), start + PERIOD);
+        assert_pending!(spawn(interval.tick()).poll());
+        advance(PERIOD / 2);
+        assert_ready_eq!(spawn(interval.tick()).poll(), start + PERIOD * 11);
+    }
+
+    #[test]
+    fn reset_restarts_period_from_now() {
+        let start = Instant::now();
+        let mut interval = interval(PERIOD);
+
+        assert_ready!(spawn(interval.tick()).poll());
+        advance(PERIOD / 2);
+        interval.reset();
+        advance(PERIOD / 2);
+        assert_pending!(spawn(interval.tick()).poll());
+        advance(PERIOD / 2);
+        assert_ready_eq!(spawn(interval.tick()).poll(), start + PERIOD + PERIOD / 2);
+    }
+}
@@ -1,21 +1,103 @@
 mod clock;
 mod instant;
+mod interval;
 mod sleep;
+mod system_time;
+mod timeout;
+
+pub mod error;
 
 pub use std::time::Duration;
 
+pub use clock::{Clock, ClockGuard, ObserverId, PendingTimer, WithClock};
 pub use instant::Instant;
-pub use sleep::Delay;
+pub use interval::{interval, interval_at, Interval, MissedTickBehavior};
+pub use sleep::Sleep;
+pub use system_time::{SystemTime, SystemTimeError, UNIX_EPOCH};
+pub use timeout::{timeout, timeout_at, Timeout};
 
-pub fn sleep_until(deadline: Instant) -> Delay {
-    Delay::new_deadline(deadline)
+#[track_caller]
+pub fn sleep_until(deadline: Instant) -> Sleep {
+    Sleep::new_deadline(deadline)
 }
 
-pub fn sleep(delay: Duration) -> Delay {
-    Delay::new_delay(delay)
+#[track_caller]
+pub fn sleep(delay: Duration) -> Sleep {
+    Sleep::new_delay(delay)
 }
 
 // Modify the time (test only)
 pub fn advance(duration: Duration) {
-    clock::advance(duration);
+    clock::current().advance(duration);
+}
+
+// Time elapsed on the current clock since it started at the epoch
+pub fn elapsed_since_start() -> Duration {
+    Instant::now().saturating_duration_since(Instant::from_std(clock::epoch()))
+}
+
+// Call `observer` with the previous and the new time on each advance of the current clock (test only)
+pub fn on_advance<F>(observer: F) -> ObserverId
+where
+    F: FnMut(Instant, Instant) + Send + 'static,
+{
+    clock::current().on_advance(observer)
+}
+
+// Run `action` once the current clock reaches `instant` (test only)
+pub fn schedule_at<F>(instant: Instant, action: F)
+where
+    F: FnOnce() + Send + 'static,
+{
+    clock::current().schedule_at(instant, action);
+}
+
+// Move the time forward to `deadline`, does nothing if it is in the past (test only)
+pub fn advance_to(deadline: Instant) {
+    clock::current().advance_to(deadline);
+}
+
+// Earliest deadline among the pending timers and scheduled actions (test only)
+pub fn next_deadline() -> Option<Instant> {
+    clock::current().next_deadline()
+}
+
+// Let the current clock run on its own, `factor` times faster than the real time (test only)
+pub fn accelerate(factor: f64) {
+    clock::current().accelerate(factor);
+}
+
+// Stop the current clock from running on its own (test only)
+pub fn pause() {
+    clock::current().pause();
+}
+
+// When enabled, `test::Spawn::poll` advances the time to the next pending
+// deadline, at most once per call, whenever no task spawned on the thread is woken (test only)
+pub fn set_auto_advance(enabled: bool) {
+    clock::current().set_auto_advance(enabled);
+}
+
+pub fn is_auto_advance() -> bool {
+    clock::current().is_auto_advance()
+}
+
+// Live timers of the current clock that have not elapsed yet (test only)
+pub fn pending_timers() -> Vec<PendingTimer> {
+    clock::current().pending_timers()
+}
+
+// Set the wall-clock time without moving the monotonic time (test only)
+pub fn set_system_time(time: SystemTime) {
+    clock::current().set_system_time(time);
+}
+
+// Step the wall-clock time forward without moving the monotonic time (test only)
+pub fn step_system_time_forward(duration: Duration) {
+    clock::current().step_system_time_forward(duration);
+}
+
+// Step the wall-clock time backward without moving the monotonic time (test only)
+pub fn step_system_time_back(duration: Duration) {
+    clock::current().step_system_time_back(duration);
 }
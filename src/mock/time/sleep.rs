@@ -1,24 +1,36 @@
 use std::future::Future;
+use std::marker::PhantomPinned;
+use std::panic::Location;
 use std::pin::Pin;
 use std::task::{Context, Poll};
 use std::time::Duration;
 
-use super::{clock, Instant};
+use super::{clock, Clock, Instant};
 
 #[derive(Debug)]
-pub struct Delay {
+pub struct Sleep {
+    clock: Clock,
     deadline: Instant,
+    id: u64,
+    // same pinning requirements as tokio's Sleep
+    _pin: PhantomPinned,
 }
 
-impl Delay {
+impl Sleep {
+    #[track_caller]
     pub(crate) fn new_deadline(deadline: Instant) -> Self {
-        Self { deadline }
+        let clock = clock::current();
+        Self {
+            id: clock.insert_timer(deadline, Location::caller()),
+            clock,
+            deadline,
+            _pin: PhantomPinned,
+        }
     }
 
+    #[track_caller]
     pub(crate) fn new_delay(delay: Duration) -> Self {
-        Self {
-            deadline: clock::now() + delay,
-        }
+        Self::new_deadline(Instant::now() + delay)
     }
 
     pub fn deadline(&self) -> Instant {
@@ -26,18 +38,203 @@ impl Delay {
     }
 
     pub fn is_elapsed(&self) -> bool {
-        clock::now() >= self.deadline
+        self.clock.is_elapsed(self.deadline)
+    }
+
+    /// Re-arm the sleep with a new deadline, even if it already completed.
+    ///
+    /// A task waiting on this sleep stays registered and is woken at the new deadline.
+    pub fn reset(self: Pin<&mut Self>, deadline: Instant) {
+        // Safety: none of the fields are structurally pinned
+        let this = unsafe { self.get_unchecked_mut() };
+        this.clock.reset(this.deadline, deadline, this.id);
+        this.deadline = deadline;
     }
 }
 
-impl Future for Delay {
+impl Future for Sleep {
     type Output = ();
 
-    fn poll(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Self::Output> {
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         if self.is_elapsed() {
+            self.clock.deregister(self.deadline, self.id);
             Poll::Ready(())
         } else {
+            self.clock.register(self.deadline, self.id, cx.waker());
             Poll::Pending
         }
     }
 }
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        self.clock.remove_timer(self.deadline, self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+    use crate::mock::test::*;
+
+    #[test]
+    fn advance_wakes_expired_sleep() {
+        let mut task = spawn(sleep(Duration::from_secs(1)));
+
+        assert_pending!(task.poll());
+        advance(Duration::from_millis(999));
+        assert!(!task.is_woken());
+        advance(Duration::from_millis(1));
+        assert!(task.is_woken());
+        assert_ready!(task.poll());
+    }
+
+    #[test]
+    fn advance_wakes_in_deadline_order() {
+        let mut late = spawn(sleep(Duration::from_secs(2)));
+        let mut early = spawn(sleep(Duration::from_secs(1)));
+
+        assert_pending!(late.poll());
+        assert_pending!(early.poll());
+        advance(Duration::from_secs(1));
+        assert!(early.is_woken());
+        assert!(!late.is_woken());
+        advance(Duration::from_secs(1));
+        assert!(late.is_woken());
+    }
+
+    #[tokio::test]
+    async fn advance_wakes_task_on_runtime() {
+        let task = tokio::spawn(sleep(Duration::from_secs(1)));
+
+        tokio::task::yield_now().await;
+        advance(Duration::from_secs(1));
+        task.await.unwrap();
+    }
+
+    #[test]
+    fn next_deadline_is_earliest_pending_timer() {
+        let mut late = spawn(sleep(Duration::from_secs(2)));
+        let mut early = spawn(sleep(Duration::from_secs(1)));
+
+        assert_eq!(next_deadline(), None);
+        assert_pending!(late.poll());
+        assert_pending!(early.poll());
+        let deadline = next_deadline().unwrap();
+        assert_eq!(deadline, Instant::now() + Duration::from_secs(1));
+        advance_to(deadline);
+        assert!(early.is_woken());
+        assert_ready!(early.poll());
+        assert_eq!(next_deadline(), Some(deadline + Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn auto_advance_jumps_to_next_deadline() {
+        set_auto_advance(true);
+        let start = Instant::now();
+        let mut task = spawn(async {
+            sleep(Duration::from_millis(250)).await;
+            sleep(Duration::from_secs(3)).await;
+        });
+
+        assert_pending!(task.poll());
+        assert_eq!(Instant::now() - start, Duration::from_millis(250));
+        assert_ready!(task.poll());
+        assert_eq!(Instant::now() - start, Duration::from_millis(3250));
+    }
+
+    #[test]
+    fn auto_advance_jumps_once_per_poll() {
+        set_auto_advance(true);
+        let start = Instant::now();
+        let mut task = spawn(async {
+            let mut interval = interval(Duration::from_secs(1));
+            loop {
+                interval.tick().await;
+            }
+        });
+
+        assert_pending!(task.poll());
+        assert_eq!(Instant::now() - start, Duration::from_secs(1));
+        assert_pending!(task.poll());
+        assert_eq!(Instant::now() - start, Duration::from_secs(2));
+    }
+
+    #[test]
+    fn auto_advance_waits_for_woken_tasks() {
+        let start = Instant::now();
+        let mut woken = spawn(sleep(Duration::from_secs(1)));
+        let mut idle = spawn(sleep(Duration::from_secs(2)));
+
+        assert_pending!(woken.poll());
+        advance(Duration::from_secs(1));
+        set_auto_advance(true);
+        assert_pending!(idle.poll());
+        assert_eq!(Instant::now() - start, Duration::from_secs(1));
+        assert_ready!(woken.poll());
+        assert_ready!(idle.poll());
+        assert_eq!(Instant::now() - start, Duration::from_secs(2));
+    }
+
+    #[test]
+    fn auto_advance_stops_at_other_tasks_deadline() {
+        let start = Instant::now();
+        let mut short = spawn(sleep(Duration::from_secs(1)));
+        let mut long = spawn(sleep(Duration::from_secs(5)));
+
+        assert_pending!(short.poll());
+        assert_pending!(long.poll());
+        set_auto_advance(true);
+        assert_pending!(long.poll());
+        assert_eq!(Instant::now() - start, Duration::from_secs(1));
+        assert!(short.is_woken());
+        assert_ready!(short.poll());
+        assert_ready!(long.poll());
+        assert_eq!(Instant::now() - start, Duration::from_secs(5));
+    }
+
+    #[test]
+    fn reset_moves_deadline() {
+        let start = Instant::now();
+        let mut task = spawn(async {
+            let sleep = sleep(Duration::from_secs(1));
+            tokio::pin!(sleep);
+            sleep.as_mut().reset(start + Duration::from_secs(3));
+            sleep.await;
+        });
+
+        assert_pending!(task.poll());
+        advance(Duration::from_secs(1));
+        assert!(!task.is_woken());
+        advance(Duration::from_secs(2));
+        assert!(task.is_woken());
+        assert_ready!(task.poll());
+    }
+
+    #[test]
+    fn reset_keeps_waiting_task_registered() {
+        let start = Instant::now();
+        let mut sleep = Box::pin(sleep(Duration::from_secs(5)));
+
+        assert_pending!(spawn(sleep.as_mut()).poll());
+        sleep.as_mut().reset(start + Duration::from_secs(1));
+        assert_eq!(next_deadline(), Some(start + Duration::from_secs(1)));
+        advance(Duration::from_secs(1));
+        assert_eq!(next_deadline(), None);
+        assert!(sleep.is_elapsed());
+    }
+
+    #[test]
+    fn reset_after_elapsed_rearms() {
+        let start = Instant::now();
+        let mut sleep = Box::pin(sleep(Duration::from_secs(1)));
+
+        advance(Duration::from_secs(1));
+        assert_ready!(spawn(sleep.as_mut()).poll());
+        sleep.as_mut().reset(start + Duration::from_secs(2));
+        assert!(!sleep.is_elapsed());
+        assert_pending!(spawn(sleep.as_mut()).poll());
+        advance(Duration::from_secs(1));
+        assert_ready!(spawn(sleep.as_mut()).poll());
+    }
+}
@@ -0,0 +1,147 @@
+use std::fmt;
+use std::ops;
+use std::time::Duration;
+
+pub use std::time::SystemTimeError;
+
+use super::clock;
+
+pub const UNIX_EPOCH: SystemTime = SystemTime {
+    std: std::time::UNIX_EPOCH,
+};
+
+/// Wall-clock time read from the current mock clock
+///
+/// It moves forward with the monotonic time when the clock is advanced, but it can
+/// also be set or stepped in either direction without affecting `Instant` or timers.
+#[derive(Clone, Copy, Eq, PartialEq, PartialOrd, Ord, Hash)]
+pub struct SystemTime {
+    std: std::time::SystemTime,
+}
+
+impl SystemTime {
+    pub const UNIX_EPOCH: SystemTime = UNIX_EPOCH;
+
+    pub fn now() -> SystemTime {
+        clock::current().system_time()
+    }
+
+    pub fn from_std(std: std::time::SystemTime) -> SystemTime {
+        SystemTime { std }
+    }
+
+    pub fn into_std(self) -> std::time::SystemTime {
+        self.std
+    }
+
+    pub fn duration_since(&self, earlier: SystemTime) -> Result<Duration, SystemTimeError> {
+        self.std.duration_since(earlier.std)
+    }
+
+    pub fn elapsed(&self) -> Result<Duration, SystemTimeError> {
+        SystemTime::now().duration_since(*self)
+    }
+
+    pub fn checked_add(&self, duration: Duration) -> Option<SystemTime> {
+        self.std.checked_add(duration).map(SystemTime::from_std)
+    }
+
+    pub fn checked_sub(&self, duration: Duration) -> Option<SystemTime> {
+        self.std.checked_sub(duration).map(SystemTime::from_std)
+    }
+}
+
+impl From<std::time::SystemTime> for SystemTime {
+    fn from(time: std::time::SystemTime) -> SystemTime {
+        SystemTime::from_std(time)
+    }
+}
+
+impl From<SystemTime> for std::time::SystemTime {
+    fn from(time: SystemTime) -> std::time::SystemTime {
+        time.into_std()
+    }
+}
+
+impl ops::Add<Duration> for SystemTime {
+    type Output = SystemTime;
+
+    fn add(self, other: Duration) -> SystemTime {
+        SystemTime::from_std(self.std + other)
+    }
+}
+
+impl ops::AddAssign<Duration> for SystemTime {
+    fn add_assign(&mut self, rhs: Duration) {
+        *self = *self + rhs;
+    }
+}
+
+impl ops::Sub<Duration> for SystemTime {
+    type Output = SystemTime;
+
+    fn sub(self, rhs: Duration) -> SystemTime {
+        SystemTime::from_std(self.std - rhs)
+    }
+}
+
+impl ops::SubAssign<Duration> for SystemTime {
+    fn sub_assign(&mut self, rhs: Duration) {
+        *self = *self - rhs;
+    }
+}
+
+impl fmt::Debug for SystemTime {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.std.fmt(fmt)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn follows_advance() {
+        set_system_time(at(1000));
+        advance(Duration::from_secs(5));
+        assert_eq!(SystemTime::now(), at(1005));
+    }
+
+    #[test]
+    fn steps_without_moving_instant() {
+        let start = Instant::now();
+        set_system_time(at(10_000));
+
+        step_system_time_back(Duration::from_secs(3600));
+        assert_eq!(SystemTime::now(), at(10_000 - 3600));
+        step_system_time_forward(Duration::from_secs(7200));
+        assert_eq!(SystemTime::now(), at(10_000 + 3600));
+        assert_eq!(Instant::now(), start);
+    }
+
+    #[test]
+    fn backward_step_is_reported_as_error() {
+        set_system_time(at(1000));
+        let stamp = SystemTime::now();
+
+        step_system_time_back(Duration::from_secs(1));
+        let err = stamp.elapsed().unwrap_err();
+        assert_eq!(err.duration(), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn skewed_clock_sees_its_offset() {
+        let reference = Clock::new();
+        let ahead = reference.named("ahead").ahead(Duration::from_secs(10));
+
+        reference.set_system_time(at(1000));
+        assert_eq!(ahead.system_time(), at(1010));
+        ahead.set_system_time(at(2000));
+        assert_eq!(reference.system_time(), at(1990));
+    }
+}
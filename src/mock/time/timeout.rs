@@ -0,0 +1,132 @@
+use std::future::{Future, IntoFuture};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use super::error::Elapsed;
+use super::{Instant, Sleep};
+
+#[track_caller]
+pub fn timeout<F>(duration: Duration, future: F) -> Timeout<F::IntoFuture>
+where
+    F: IntoFuture,
+{
+    let deadline = Instant::now()
+        .checked_add(duration)
+        .unwrap_or_else(far_future);
+
+    Timeout::new(future.into_future(), deadline)
+}
+
+#[track_caller]
+pub fn timeout_at<F>(deadline: Instant, future: F) -> Timeout<F::IntoFuture>
+where
+    F: IntoFuture,
+{
+    Timeout::new(future.into_future(), deadline)
+}
+
+// roughly 30 years from now, same as tokio
+fn far_future() -> Instant {
+    Instant::now() + Duration::from_secs(86400 * 365 * 30)
+}
+
+#[derive(Debug)]
+pub struct Timeout<T> {
+    value: T,
+    delay: Sleep,
+}
+
+impl<T> Timeout<T> {
+    #[track_caller]
+    fn new(value: T, deadline: Instant) -> Self {
+        Self {
+            value,
+            delay: Sleep::new_deadline(deadline),
+        }
+    }
+
+    pub fn get_ref(&self) -> &T {
+        &self.value
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+}
+
+impl<T> Future for Timeout<T>
+where
+    T: Future,
+{
+    type Output = Result<T::Output, Elapsed>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // Safety: `value` and `delay` are never moved out of the pinned `Timeout`
+        let this = unsafe { self.get_unchecked_mut() };
+        let value = unsafe { Pin::new_unchecked(&mut this.value) };
+        let delay = unsafe { Pin::new_unchecked(&mut this.delay) };
+
+        if let Poll::Ready(output) = value.poll(cx) {
+            return Poll::Ready(Ok(output));
+        }
+
+        match delay.poll(cx) {
+            Poll::Ready(()) => Poll::Ready(Err(Elapsed::new())),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+    use crate::mock::sync::oneshot;
+    use crate::mock::test::*;
+
+    #[test]
+    fn completes_before_deadline() {
+        let (tx, rx) = oneshot::channel::<u32>();
+        let mut task = spawn(timeout(Duration::from_secs(1), rx));
+
+        assert_pending!(task.poll());
+        advance(Duration::from_millis(999));
+        assert_pending!(task.poll());
+        tx.send(42).unwrap();
+        assert_ready_eq!(task.poll(), Ok(Ok(42)));
+    }
+
+    #[test]
+    fn elapses_on_advance() {
+        let (_tx, rx) = oneshot::channel::<u32>();
+        let mut task = spawn(timeout(Duration::from_secs(1), rx));
+
+        assert_pending!(task.poll());
+        advance(Duration::from_secs(1));
+        assert!(task.is_woken());
+        assert_ready_eq!(task.poll(), Err(error::Elapsed::new()));
+    }
+
+    #[test]
+    fn timeout_at_uses_deadline() {
+        let deadline = Instant::now() + Duration::from_secs(2);
+        let mut task = spawn(timeout_at(deadline, std::future::pending::<()>()));
+
+        assert_pending!(task.poll());
+        advance(Duration::from_secs(1));
+        assert_pending!(task.poll());
+        advance(Duration::from_secs(1));
+        assert_ready_err!(task.poll());
+    }
+
+    #[test]
+    fn ready_future_wins_over_elapsed_deadline() {
+        let mut task = spawn(timeout(Duration::from_secs(0), std::future::ready(1)));
+
+        assert_ready_eq!(task.poll(), Ok(1));
+    }
+}
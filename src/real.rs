@@ -21,10 +21,22 @@ pub mod time {
     use tokio::time;
 
     pub use std::time::Duration; // Re-export in tokio
+    pub use std::time::{SystemTime, SystemTimeError, UNIX_EPOCH};
 
     pub use time::sleep;
     pub use time::sleep_until;
     pub use time::Instant;
+    pub use time::Sleep;
+
+    pub use time::interval;
+    pub use time::interval_at;
+    pub use time::Interval;
+    pub use time::MissedTickBehavior;
+
+    pub use time::error;
+    pub use time::timeout;
+    pub use time::timeout_at;
+    pub use time::Timeout;
 }
 
 pub mod sync {
@@ -32,17 +44,31 @@ pub mod sync {
         use tokio::sync::mpsc;
 
         pub use mpsc::channel;
+        pub use mpsc::OwnedPermit;
+        pub use mpsc::Permit;
         pub use mpsc::Receiver;
         pub use mpsc::Sender;
+        pub use mpsc::WeakSender;
 
         pub use mpsc::unbounded_channel;
         pub use mpsc::UnboundedReceiver;
         pub use mpsc::UnboundedSender;
+        pub use mpsc::WeakUnboundedSender;
+
+        #[cfg(feature = "futures")]
+        pub use tokio_stream::wrappers::ReceiverStream;
+        #[cfg(feature = "futures")]
+        pub use tokio_stream::wrappers::UnboundedReceiverStream;
+        #[cfg(feature = "futures")]
+        pub use tokio_util::sync::PollSendError;
+        #[cfg(feature = "futures")]
+        pub use tokio_util::sync::PollSender;
 
         pub mod error {
             use tokio::sync::mpsc::error;
 
             pub use error::SendError;
+            pub use error::SendTimeoutError;
             pub use error::TryRecvError;
             pub use error::TrySendError;
         }